@@ -0,0 +1,31 @@
+use serde_json::{Map, Value};
+
+// Binary payloads become `{"$bytes": "<lowercase hex>"}` so they stay distinct
+// from text and compare byte for byte.
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+pub(crate) fn bytes_to_value(bytes: &[u8]) -> Value {
+    tagged("$bytes", Value::String(to_hex(bytes)))
+}
+
+// Keys starting with `$` are reserved for wrappers like the one above and
+// TOML's datetimes, so map keys
+// from the input that start with `$` get one more, e.g. `$bytes` becomes
+// `$$bytes`. A decoded map then never looks like a wrapped value.
+pub(crate) fn escape_key(key: String) -> String {
+    if key.starts_with('$') {
+        format!("${}", key)
+    } else {
+        key
+    }
+}
+
+pub(crate) fn tagged(tag: &str, value: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(tag.to_string(), value);
+    Value::Object(map)
+}
+
+#[cfg(any(feature = "cbor", feature = "msgpack"))]
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
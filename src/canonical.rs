@@ -0,0 +1,212 @@
+use crate::diff::number_key;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// Serializes `value` with the JSON Canonicalization Scheme (RFC 8785):
+/// no whitespace, object members sorted by the UTF-16 code units of their
+/// keys, minimal string escaping and numbers printed the way ECMAScript
+/// prints IEEE 754 doubles.
+///
+/// Like JCS itself this treats every number as a double, so `1` and `1.0`
+/// canonicalize identically and integers beyond 2^53 may lose precision.
+/// Use [`fingerprint`] to test whether `JsonDiff` would consider two
+/// documents equal. With the `arbitrary_precision` feature a number can be
+/// too large for a double; as JCS requires, that is an error.
+pub fn canonicalize(value: &Value) -> Result<String, String> {
+    enum Task<'a> {
+        Value(&'a Value),
+        Key(&'a str),
+        Text(&'static str),
+    }
+
+    let mut out = String::new();
+    let mut tasks = vec![Task::Value(value)];
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Text(text) => out.push_str(text),
+            Task::Key(key) => {
+                write_string(&mut out, key);
+                out.push(':');
+            }
+            Task::Value(Value::Null) => out.push_str("null"),
+            Task::Value(Value::Bool(b)) => out.push_str(if *b { "true" } else { "false" }),
+            Task::Value(Value::Number(n)) => match n.as_f64() {
+                Some(f) => out.push_str(&format_number(f)),
+                None => return Err(format!("number {} is out of range for a double", n)),
+            },
+            Task::Value(Value::String(s)) => write_string(&mut out, s),
+            Task::Value(Value::Array(items)) => {
+                out.push('[');
+                tasks.push(Task::Text("]"));
+                for (i, item) in items.iter().enumerate().rev() {
+                    tasks.push(Task::Value(item));
+                    if i > 0 {
+                        tasks.push(Task::Text(","));
+                    }
+                }
+            }
+            Task::Value(Value::Object(map)) => {
+                out.push('{');
+                tasks.push(Task::Text("}"));
+                for (i, (key, item)) in sorted_members(map).into_iter().enumerate().rev() {
+                    tasks.push(Task::Value(item));
+                    tasks.push(Task::Key(key));
+                    if i > 0 {
+                        tasks.push(Task::Text(","));
+                    }
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// A 128-bit content hash of a JSON value, from [`fingerprint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fingerprint(pub u128);
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+/// Computes a stable content hash of `value`.
+///
+/// Two values that `JsonDiff::new()` reports no differences between always
+/// have the same fingerprint, and the fingerprint does not depend on object
+/// key order, platform or process, so it can be stored and compared later.
+/// Different values collide only by chance; the hash (FNV-1a) is not
+/// cryptographic, so do not rely on it against adversarial input.
+///
+/// Numbers are hashed the way `JsonDiff` compares them, so fingerprints
+/// taken with and without the `arbitrary_precision` feature differ.
+pub fn fingerprint(value: &Value) -> Fingerprint {
+    enum Task<'a> {
+        Value(&'a Value),
+        Key(&'a str),
+    }
+
+    let mut hasher = Fnv128::new();
+    let mut tasks = vec![Task::Value(value)];
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Key(key) => hasher.write_str(key),
+            Task::Value(Value::Null) => hasher.write(&[0]),
+            Task::Value(Value::Bool(b)) => hasher.write(&[1, *b as u8]),
+            Task::Value(Value::Number(n)) => {
+                hasher.write(&[2]);
+                hasher.write_str(&number_key(n));
+            }
+            Task::Value(Value::String(s)) => {
+                hasher.write(&[3]);
+                hasher.write_str(s);
+            }
+            Task::Value(Value::Array(items)) => {
+                hasher.write(&[4]);
+                hasher.write_len(items.len());
+                tasks.extend(items.iter().rev().map(Task::Value));
+            }
+            Task::Value(Value::Object(map)) => {
+                hasher.write(&[5]);
+                hasher.write_len(map.len());
+                for (key, item) in sorted_members(map).into_iter().rev() {
+                    tasks.push(Task::Value(item));
+                    tasks.push(Task::Key(key));
+                }
+            }
+        }
+    }
+    Fingerprint(hasher.finish())
+}
+
+fn sorted_members(map: &Map<String, Value>) -> Vec<(&str, &Value)> {
+    let mut members: Vec<_> = map.iter().map(|(k, v)| (k.as_str(), v)).collect();
+    members.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+    members
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\u{c}' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Formats a double like ECMAScript's `Number.prototype.toString`.
+fn format_number(f: f64) -> String {
+    if f == 0.0 {
+        return "0".to_string();
+    }
+
+    // `{:e}` gives the shortest digits that round-trip, e.g. "1.25e-7".
+    let scientific = format!("{:e}", f.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits: String = mantissa.chars().filter(|&c| c != '.').collect();
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>().unwrap_or(0) + 1;
+
+    let body = if k <= n && n <= 21 {
+        format!("{}{}", digits, "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", "0".repeat(-n as usize), digits)
+    } else {
+        let sign = if n > 0 { '+' } else { '-' };
+        let fraction = if k > 1 {
+            format!(".{}", &digits[1..])
+        } else {
+            String::new()
+        };
+        format!("{}{}e{}{}", &digits[..1], fraction, sign, (n - 1).abs())
+    };
+
+    if f < 0.0 {
+        format!("-{}", body)
+    } else {
+        body
+    }
+}
+
+struct Fnv128(u128);
+
+impl Fnv128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u128;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn write_len(&mut self, len: usize) {
+        self.write(&(len as u64).to_le_bytes());
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.write_len(s.len());
+        self.write(s.as_bytes());
+    }
+
+    fn finish(&self) -> u128 {
+        self.0
+    }
+}
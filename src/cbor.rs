@@ -0,0 +1,80 @@
+use crate::bytes::{bytes_to_value, escape_key, tagged};
+use ciborium::value::Value as CborValue;
+use serde_json::{json, Map, Number, Value};
+
+/// Decodes a single CBOR data item into the JSON data model.
+///
+/// Byte strings become `{"$bytes": "<hex>"}` and tagged items become
+/// `{"$tag": {"tag": <n>, "value": <item>}}`. Map keys must be text, and
+/// keys starting with `$` get another `$` prepended, so that a map such as
+/// `{"$bytes": "0102"}` stays distinct from a byte string.
+pub fn from_cbor_slice(input: &[u8]) -> Result<Value, String> {
+    let mut reader = input;
+    let value: CborValue = ciborium::from_reader(&mut reader).map_err(|e| e.to_string())?;
+    if !reader.is_empty() {
+        return Err(format!("{} trailing byte(s) after CBOR item", reader.len()));
+    }
+    cbor_to_value(value, "")
+}
+
+fn cbor_to_value(value: CborValue, path: &str) -> Result<Value, String> {
+    match value {
+        CborValue::Null => Ok(Value::Null),
+        CborValue::Bool(b) => Ok(Value::Bool(b)),
+        CborValue::Text(s) => Ok(Value::String(s)),
+        CborValue::Bytes(bytes) => Ok(bytes_to_value(&bytes)),
+        CborValue::Integer(i) => {
+            let i = i128::from(i);
+            if let Ok(u) = u64::try_from(i) {
+                Ok(Value::Number(u.into()))
+            } else if let Ok(n) = i64::try_from(i) {
+                Ok(Value::Number(n.into()))
+            } else {
+                Err(format!("integer {} at '{}' is out of range", i, path))
+            }
+        }
+        CborValue::Float(f) => Number::from_f64(f)
+            .map(Value::Number)
+            .ok_or_else(|| format!("float {} at '{}' cannot be represented in JSON", f, path)),
+        CborValue::Tag(tag, inner) => Ok(tagged(
+            "$tag",
+            json!({"tag": tag, "value": cbor_to_value(*inner, path)?}),
+        )),
+        CborValue::Array(items) => items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let item_path = if path.is_empty() {
+                    format!("[{}]", i)
+                } else {
+                    format!("{}[{}]", path, i)
+                };
+                cbor_to_value(item, &item_path)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        CborValue::Map(entries) => {
+            let mut map = Map::new();
+            for (key, item) in entries {
+                let key = match key {
+                    CborValue::Text(key) => escape_key(key),
+                    other => {
+                        return Err(format!("non-text map key {:?} at '{}'", other, path));
+                    }
+                };
+                let item_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                if map.contains_key(&key) {
+                    return Err(format!("duplicate map key at '{}'", item_path));
+                }
+                let item = cbor_to_value(item, &item_path)?;
+                map.insert(key, item);
+            }
+            Ok(Value::Object(map))
+        }
+        other => Err(format!("unsupported CBOR value {:?} at '{}'", other, path)),
+    }
+}
@@ -0,0 +1,71 @@
+use crate::datetime::parse_timestamp;
+use crate::diff::numbers_equal;
+use serde_json::{Number, Value};
+use std::fmt;
+
+/// A rule under which two values of different JSON types compare equal,
+/// enabled with [`JsonDiff::coerce`] or [`JsonDiff::coerce_at`].
+///
+/// Each rule works in both directions.
+///
+/// [`JsonDiff::coerce`]: crate::JsonDiff::coerce
+/// [`JsonDiff::coerce_at`]: crate::JsonDiff::coerce_at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Coercion {
+    /// A string holding a JSON number equals that number, so `"42"` equals
+    /// `42`. The numbers are compared as usual, so `"42.0"` equals `42.0`
+    /// but not `42`.
+    StringNumber,
+    /// `"true"` equals `true` and `"false"` equals `false`.
+    StringBool,
+    /// A number of seconds since the Unix epoch equals an RFC 3339
+    /// timestamp string for the same instant, so `0` equals
+    /// `"1970-01-01T00:00:00Z"`.
+    EpochTimestamp,
+}
+
+impl Coercion {
+    /// Returns whether `v1` and `v2` are equal once this rule is applied.
+    pub(crate) fn equal(self, v1: &Value, v2: &Value) -> bool {
+        let (text, other) = match (v1, v2) {
+            (Value::String(text), other) | (other, Value::String(text)) => (text, other),
+            _ => return false,
+        };
+        match (self, other) {
+            (Coercion::StringNumber, Value::Number(n)) => text
+                .parse::<Number>()
+                .is_ok_and(|parsed| numbers_equal(&parsed, n)),
+            (Coercion::StringBool, Value::Bool(b)) => text == if *b { "true" } else { "false" },
+            (Coercion::EpochTimestamp, Value::Number(n)) => {
+                epoch_seconds(n).is_some_and(|epoch| parse_timestamp(text) == Some(epoch))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Coercion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Coercion::StringNumber => "string-number",
+            Coercion::StringBool => "string-bool",
+            Coercion::EpochTimestamp => "epoch-timestamp",
+        })
+    }
+}
+
+// Splits a number of seconds into whole seconds and nanoseconds, rounding
+// fractional seconds to the nearest nanosecond.
+fn epoch_seconds(n: &Number) -> Option<(i64, u32)> {
+    if let Some(seconds) = n.as_i64() {
+        return Some((seconds, 0));
+    }
+    let f = n.as_f64().filter(|f| f.is_finite())?;
+    let seconds = f.floor();
+    let nanosecond = ((f - seconds) * 1e9).round() as u32;
+    if nanosecond >= 1_000_000_000 {
+        Some((seconds as i64 + 1, 0))
+    } else {
+        Some((seconds as i64, nanosecond))
+    }
+}
@@ -0,0 +1,37 @@
+use serde_json::Value;
+
+/// The verdict of a [`ValueComparator`] on a pair of values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    /// The values are equal; nothing is reported and they are not descended
+    /// into.
+    Equal,
+    /// The values differ; a single `Modified` (or `TypeChanged`) diff is
+    /// reported for the pair, without descending into them.
+    Different,
+    /// No opinion; the next matching comparator decides, and the built-in
+    /// comparison if none does.
+    Defer,
+}
+
+/// A custom equality rule, registered with [`JsonDiff::comparator_at`] or
+/// [`JsonDiff::comparator_for`].
+///
+/// Comparators are consulted before the built-in comparison, for every pair
+/// of values they are registered for, including while matching arrays with
+/// `ignore_order`. Parallel diffs call them from the worker threads, so
+/// comparators must be `Send + Sync`. Any
+/// `Fn(&str, &Value, &Value) -> Comparison + Send + Sync` closure is a
+/// comparator.
+///
+/// [`JsonDiff::comparator_at`]: crate::JsonDiff::comparator_at
+/// [`JsonDiff::comparator_for`]: crate::JsonDiff::comparator_for
+pub trait ValueComparator: Send + Sync {
+    fn compare(&self, path: &str, old: &Value, new: &Value) -> Comparison;
+}
+
+impl<F: Fn(&str, &Value, &Value) -> Comparison + Send + Sync> ValueComparator for F {
+    fn compare(&self, path: &str, old: &Value, new: &Value) -> Comparison {
+        self(path, old, new)
+    }
+}
@@ -0,0 +1,104 @@
+// Proleptic Gregorian calendar arithmetic, after Howard Hinnant's
+// `days_from_civil` / `civil_from_days` algorithms.
+
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(feature = "toml")]
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(feature = "toml")]
+pub(crate) fn format_time(hour: u32, minute: u32, second: u32, nanosecond: u32) -> String {
+    let mut text = format!("{:02}:{:02}:{:02}", hour, minute, second);
+    if nanosecond > 0 {
+        let fraction = format!("{:09}", nanosecond);
+        text.push('.');
+        text.push_str(fraction.trim_end_matches('0'));
+    }
+    text
+}
+
+/// Parses an RFC 3339 timestamp such as `2024-05-01T12:30:00.25+02:00` into
+/// seconds and nanoseconds since the Unix epoch. A leap second (`:60`) is
+/// read as the first second of the next minute.
+pub(crate) fn parse_timestamp(text: &str) -> Option<(i64, u32)> {
+    let byte = |index: usize| text.as_bytes().get(index).copied();
+
+    let year = digits_at(text, 0, 4)?;
+    let month = digits_at(text, 5, 2)?;
+    let day = digits_at(text, 8, 2)?;
+    let hour = digits_at(text, 11, 2)?;
+    let minute = digits_at(text, 14, 2)?;
+    let second = digits_at(text, 17, 2)?;
+    if byte(4) != Some(b'-')
+        || byte(7) != Some(b'-')
+        || !matches!(byte(10), Some(b'T' | b't' | b' '))
+        || byte(13) != Some(b':')
+        || byte(16) != Some(b':')
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+
+    let mut rest = &text[19..];
+    let mut nanosecond = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let len = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 || len > 9 {
+            return None;
+        }
+        nanosecond = fraction[..len].parse::<u32>().ok()? * 10u32.pow(9 - len as u32);
+        rest = &fraction[len..];
+    }
+
+    let offset_minutes = match rest.as_bytes() {
+        b"Z" | b"z" => 0,
+        [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+            let (hours, minutes) = (digits_at(rest, 1, 2)?, digits_at(rest, 4, 2)?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            let offset = hours * 60 + minutes;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return None,
+    };
+
+    let days = days_from_civil(year, month as u32, day as u32);
+    let seconds = days * 86_400 + hour * 3600 + (minute - offset_minutes) * 60 + second;
+    Some((seconds, nanosecond))
+}
+
+fn digits_at(text: &str, start: usize, len: usize) -> Option<i64> {
+    let digits = text.get(start..start + len)?;
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
@@ -1,22 +1,138 @@
-use serde_json::Value;
-use std::fmt;
+use crate::coercion::Coercion;
+use crate::comparator::{Comparison, ValueComparator};
+use crate::equivalence::Equivalence;
+use crate::limits::{Budget, CancellationToken, DiffReport, Limit};
+use crate::path::PathPattern;
+use crate::prepared::{HashNode, PreparedDocument};
+use crate::progress::{count_nodes, ProgressObserver};
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::Instant;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum DiffType {
     Added,
     Removed,
     Modified,
     Moved,
+    Truncated,
+    Copied,
+    /// The value was replaced by one of a different JSON type, e.g. a string
+    /// by a number or an object by `null`.
+    TypeChanged {
+        old: JsonType,
+        new: JsonType,
+    },
+    /// The values only compare equal under the given coercion. Reported
+    /// when [`JsonDiff::report_coercions`] is enabled.
+    Coerced(Coercion),
+}
+
+/// The type of a JSON value, as carried by [`DiffType::TypeChanged`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JsonType {
+    Null,
+    Bool,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl JsonType {
+    pub fn of(value: &Value) -> JsonType {
+        match value {
+            Value::Null => JsonType::Null,
+            Value::Bool(_) => JsonType::Bool,
+            Value::Number(_) => JsonType::Number,
+            Value::String(_) => JsonType::String,
+            Value::Array(_) => JsonType::Array,
+            Value::Object(_) => JsonType::Object,
+        }
+    }
+}
+
+impl fmt::Display for JsonType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JsonType::Null => "null",
+            JsonType::Bool => "boolean",
+            JsonType::Number => "number",
+            JsonType::String => "string",
+            JsonType::Array => "array",
+            JsonType::Object => "object",
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Diff {
     pub path: String,
+    /// Where a `Moved` or `Copied` value came from; `path` is where it ended
+    /// up.
+    pub from_path: Option<String>,
     pub diff_type: DiffType,
     pub old_value: Option<Value>,
     pub new_value: Option<Value>,
 }
 
+/// A difference that borrows its values from the documents being compared.
+///
+/// Produced by [`JsonDiff::diff_ref`] and passed to every [`DiffSink`]; call
+/// [`DiffRef::to_owned`] to keep it beyond the lifetime of the inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffRef<'a> {
+    pub path: String,
+    pub from_path: Option<String>,
+    pub diff_type: DiffType,
+    pub old_value: Option<&'a Value>,
+    pub new_value: Option<&'a Value>,
+}
+
+impl DiffRef<'_> {
+    pub fn to_owned(&self) -> Diff {
+        Diff {
+            path: self.path.clone(),
+            from_path: self.from_path.clone(),
+            diff_type: self.diff_type.clone(),
+            old_value: self.old_value.map(clone_value),
+            new_value: self.new_value.map(clone_value),
+        }
+    }
+
+    pub fn into_owned(self) -> Diff {
+        Diff {
+            path: self.path,
+            from_path: self.from_path,
+            diff_type: self.diff_type,
+            old_value: self.old_value.map(clone_value),
+            new_value: self.new_value.map(clone_value),
+        }
+    }
+}
+
+impl Diff {
+    pub fn borrowed(&self) -> DiffRef<'_> {
+        DiffRef {
+            path: self.path.clone(),
+            from_path: self.from_path.clone(),
+            diff_type: self.diff_type.clone(),
+            old_value: self.old_value.as_ref(),
+            new_value: self.new_value.as_ref(),
+        }
+    }
+}
+
 impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.borrowed(), f)
+    }
+}
+
+impl fmt::Display for DiffRef<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.diff_type {
             DiffType::Added => {
@@ -32,26 +148,175 @@ impl fmt::Display for Diff {
                     self.path, self.old_value, self.new_value
                 )
             }
-            DiffType::Moved => {
+            DiffType::Moved => match &self.from_path {
+                Some(from_path) => write!(f, "Moved: '{}' -> '{}'", from_path, self.path),
+                None => write!(f, "Moved: {} -> {}", self.path, self.new_value.unwrap()),
+            },
+            DiffType::Truncated => {
                 write!(
                     f,
-                    "Moved: {} -> {}",
-                    self.path,
-                    self.new_value.as_ref().unwrap()
+                    "Truncated at '{}': subtrees differ below max depth",
+                    self.path
+                )
+            }
+            DiffType::Copied => write!(
+                f,
+                "Copied: '{}' -> '{}'",
+                self.from_path.as_deref().unwrap_or_default(),
+                self.path
+            ),
+            DiffType::TypeChanged { old, new } => {
+                write!(
+                    f,
+                    "Type changed at '{}' from {} to {}: {:?} -> {:?}",
+                    self.path, old, new, self.old_value, self.new_value
+                )
+            }
+            DiffType::Coerced(coercion) => {
+                write!(
+                    f,
+                    "Coerced at '{}' ({}): {:?} -> {:?}",
+                    self.path, coercion, self.old_value, self.new_value
                 )
             }
         }
     }
 }
 
+/// Receives differences one at a time from [`JsonDiff::diff_with`].
+///
+/// Returning `ControlFlow::Break(())` stops the traversal. `Vec<Diff>`,
+/// `Vec<DiffRef>` and closures of type `FnMut(DiffRef) -> ControlFlow<()>`
+/// implement this trait.
+pub trait DiffSink<'a> {
+    fn on_diff(&mut self, diff: DiffRef<'a>) -> ControlFlow<()>;
+}
+
+impl<'a> DiffSink<'a> for Vec<Diff> {
+    fn on_diff(&mut self, diff: DiffRef<'a>) -> ControlFlow<()> {
+        self.push(diff.into_owned());
+        ControlFlow::Continue(())
+    }
+}
+
+impl<'a> DiffSink<'a> for Vec<DiffRef<'a>> {
+    fn on_diff(&mut self, diff: DiffRef<'a>) -> ControlFlow<()> {
+        self.push(diff);
+        ControlFlow::Continue(())
+    }
+}
+
+impl<'a, F: FnMut(DiffRef<'a>) -> ControlFlow<()>> DiffSink<'a> for F {
+    fn on_diff(&mut self, diff: DiffRef<'a>) -> ControlFlow<()> {
+        self(diff)
+    }
+}
+
+struct LimitedSink<'s, S: ?Sized> {
+    inner: &'s mut S,
+    remaining: usize,
+    budget: &'s Budget,
+}
+
+impl<'a, S: DiffSink<'a> + ?Sized> DiffSink<'a> for LimitedSink<'_, S> {
+    fn on_diff(&mut self, diff: DiffRef<'a>) -> ControlFlow<()> {
+        if self.remaining == 0 {
+            return self.budget.stop(Limit::Diffs);
+        }
+        self.remaining -= 1;
+        self.inner.on_diff(diff)?;
+        if self.remaining == 0 {
+            return self.budget.stop(Limit::Diffs);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+// Turns each added non-empty container that also occurs somewhere in `old`
+// into a `Copied` diff. The index of `old` is only built once an added
+// container shows up.
+struct CopySink<'s, 'a, S: ?Sized> {
+    inner: &'s mut S,
+    differ: &'s JsonDiff,
+    old: &'a Value,
+    sources: Option<HashMap<u64, Vec<(String, &'a Value)>>>,
+}
+
+impl<'a, S: DiffSink<'a> + ?Sized> DiffSink<'a> for CopySink<'_, 'a, S> {
+    fn on_diff(&mut self, mut diff: DiffRef<'a>) -> ControlFlow<()> {
+        if let (DiffType::Added, Some(value)) = (&diff.diff_type, diff.new_value) {
+            if is_nonempty_container(value) {
+                let structural = self.differ.equality_is_structural();
+                let sources = self
+                    .sources
+                    .get_or_insert_with(|| copy_sources(self.old, structural));
+                let key = if structural {
+                    HashNode::build(value).hash
+                } else {
+                    0
+                };
+                let source = sources.get(&key).and_then(|candidates| {
+                    candidates
+                        .iter()
+                        .find(|(_, source)| self.differ.equal_at(source, value, &diff.path))
+                });
+                if let Some((path, source)) = source {
+                    diff.diff_type = DiffType::Copied;
+                    diff.from_path = Some(path.clone());
+                    diff.old_value = Some(*source);
+                }
+            }
+        }
+        self.inner.on_diff(diff)
+    }
+}
+
+// What a registered comparator applies to.
+enum ComparatorTarget {
+    Path(PathPattern),
+    Type(JsonType),
+}
+
 pub struct JsonDiff {
-    ignore_order: bool,
+    pub(crate) ignore_order: bool,
+    pub(crate) max_diffs: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    max_nodes: Option<usize>,
+    pub(crate) rename_threshold: Option<f64>,
+    detect_copies: bool,
+    coercions: Vec<(Option<PathPattern>, Coercion)>,
+    report_coercions: bool,
+    equivalences: Vec<(Option<PathPattern>, Equivalence)>,
+    defaults: HashMap<String, Value>,
+    path_defaults: Vec<(PathPattern, Value)>,
+    comparators: Vec<(ComparatorTarget, Arc<dyn ValueComparator>)>,
+    deadline: Option<Instant>,
+    cancellation: Option<CancellationToken>,
+    progress: Option<Arc<dyn ProgressObserver>>,
+    #[cfg(feature = "parallel")]
+    parallel_threshold: usize,
 }
 
 impl JsonDiff {
     pub fn new() -> Self {
         Self {
             ignore_order: false,
+            max_diffs: None,
+            max_depth: None,
+            max_nodes: None,
+            rename_threshold: None,
+            detect_copies: false,
+            coercions: Vec::new(),
+            report_coercions: false,
+            equivalences: Vec::new(),
+            defaults: HashMap::new(),
+            path_defaults: Vec::new(),
+            comparators: Vec::new(),
+            deadline: None,
+            cancellation: None,
+            progress: None,
+            #[cfg(feature = "parallel")]
+            parallel_threshold: 1024,
         }
     }
 
@@ -60,234 +325,1257 @@ impl JsonDiff {
         self
     }
 
+    /// Stops the traversal as soon as `max` differences have been reported.
+    /// Reaching the limit counts as truncation even if nothing else differs,
+    /// since the rest of the documents is never compared.
+    pub fn max_diffs(mut self, max: usize) -> Self {
+        self.max_diffs = Some(max);
+        self
+    }
+
+    /// Containers nested deeper than `depth` (the root is depth 0) are not
+    /// descended into. If they still differ, a single `Truncated` diff is
+    /// reported at the deepest path reached, carrying both subtrees.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Stops the traversal after `max` pairs of values have been compared.
+    pub fn max_nodes(mut self, max: usize) -> Self {
+        self.max_nodes = Some(max);
+        self
+    }
+
+    /// Reports a key that was renamed within an object as a single `Moved`
+    /// diff instead of a `Removed` and an `Added` one.
+    ///
+    /// A key only the old object has is paired with the key only the new
+    /// object has whose value is most alike, as scored by
+    /// [`JsonDiff::similarity`] with this differ's options at the new path,
+    /// if that score is at least `min_similarity`. Pass 1.0 to pair only
+    /// equal values. The `Moved` diff carries the old path in `from_path`
+    /// and is followed by any differences between the two values, reported
+    /// under the new path.
+    pub fn detect_renames(mut self, min_similarity: f64) -> Self {
+        self.rename_threshold = Some(min_similarity);
+        self
+    }
+
+    /// Reports an added array or object that is equal to a value found
+    /// anywhere in the old document, under this differ's options, as
+    /// `Copied`, like a JSON Patch `copy` operation. `from_path` is the path
+    /// of the first such value in document order and `old_value` holds it.
+    /// Empty containers and scalars are always reported as `Added`, and
+    /// `diff_readers` does not detect copies since it does not keep the old
+    /// document.
+    pub fn detect_copies(mut self, detect: bool) -> Self {
+        self.detect_copies = detect;
+        self
+    }
+
+    /// Treats values of different types as equal wherever they match under
+    /// `coercion`. Can be called more than once to enable several rules.
+    pub fn coerce(mut self, coercion: Coercion) -> Self {
+        self.coercions.push((None, coercion));
+        self
+    }
+
+    /// Like [`JsonDiff::coerce`], but only at paths matching `pattern`.
+    pub fn coerce_at(mut self, pattern: impl Into<PathPattern>, coercion: Coercion) -> Self {
+        self.coercions.push((Some(pattern.into()), coercion));
+        self
+    }
+
+    /// Reports each pair of values that only compared equal through a
+    /// coercion as a `Coerced` diff naming the rule. These diffs do not make
+    /// `is_equal` false or lower `similarity`. Values that are matched up
+    /// while comparing arrays with `ignore_order` are not reported.
+    pub fn report_coercions(mut self, report: bool) -> Self {
+        self.report_coercions = report;
+        self
+    }
+
+    /// Treats null, empty and missing values as equal as described by
+    /// `equivalence`. Can be called more than once to enable both rules.
+    pub fn equivalence(mut self, equivalence: Equivalence) -> Self {
+        self.equivalences.push((None, equivalence));
+        self
+    }
+
+    /// Like [`JsonDiff::equivalence`], but only at paths matching `pattern`.
+    pub fn equivalence_at(
+        mut self,
+        pattern: impl Into<PathPattern>,
+        equivalence: Equivalence,
+    ) -> Self {
+        self.equivalences.push((Some(pattern.into()), equivalence));
+        self
+    }
+
+    /// Treats an object member that one side lacks as holding its default,
+    /// so it is only reported if the other side's value differs from it.
+    ///
+    /// `defaults` is shaped like the documents being compared: every member
+    /// of every object in it, at any depth, is the default for the member
+    /// at the same path. Arrays in `defaults` are default values as a whole;
+    /// use [`JsonDiff::default_at`] for members of array elements. Calling
+    /// this again adds to the defaults, replacing those at the same paths.
+    pub fn defaults(mut self, defaults: Value) -> Self {
+        let mut stack = vec![(String::new(), defaults)];
+        while let Some((path, value)) = stack.pop() {
+            if let Value::Object(map) = value {
+                for (key, member) in map {
+                    let member_path = key_path(&path, &key);
+                    if member.is_object() {
+                        stack.push((member_path.clone(), member.clone()));
+                    }
+                    self.defaults.insert(member_path, member);
+                }
+            }
+        }
+        self
+    }
+
+    /// Like [`JsonDiff::defaults`], but gives the default for members at
+    /// paths matching `pattern`, e.g. `items[*].enabled`. Defaults from
+    /// [`JsonDiff::defaults`] take precedence, then earlier patterns.
+    pub fn default_at(mut self, pattern: impl Into<PathPattern>, value: Value) -> Self {
+        self.path_defaults.push((pattern.into(), value));
+        self
+    }
+
+    /// Decides equality with `comparator` for values at paths matching
+    /// `pattern`. Comparators are tried in the order they were registered.
+    pub fn comparator_at<C: ValueComparator + 'static>(
+        mut self,
+        pattern: impl Into<PathPattern>,
+        comparator: C,
+    ) -> Self {
+        self.comparators
+            .push((ComparatorTarget::Path(pattern.into()), Arc::new(comparator)));
+        self
+    }
+
+    /// Decides equality with `comparator` wherever both values are of type
+    /// `json_type`. Comparators are tried in the order they were registered.
+    pub fn comparator_for<C: ValueComparator + 'static>(
+        mut self,
+        json_type: JsonType,
+        comparator: C,
+    ) -> Self {
+        self.comparators
+            .push((ComparatorTarget::Type(json_type), Arc::new(comparator)));
+        self
+    }
+
+    /// Stops the traversal once `deadline` has passed. The clock is checked
+    /// every few hundred nodes, so the diff may run slightly past it.
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stops the traversal soon after `token` is cancelled.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Reports the number of nodes compared so far to `observer` every few
+    /// hundred nodes, and once more when the diff ends. Sizing the inputs
+    /// for the estimated total costs one extra pass over both of them.
+    pub fn progress<P: ProgressObserver + 'static>(mut self, observer: P) -> Self {
+        self.progress = Some(Arc::new(observer));
+        self
+    }
+
+    /// Arrays and objects with at least `threshold` children are diffed on
+    /// the rayon thread pool. Output order is the same as a sequential run.
+    #[cfg(feature = "parallel")]
+    pub fn parallel_threshold(mut self, threshold: usize) -> Self {
+        self.parallel_threshold = threshold;
+        self
+    }
+
+    /// Returns whether `diff(v1, v2)` would be empty, stopping at the first
+    /// difference instead of collecting them. The diff count, node, time and
+    /// cancellation limits do not apply, since a partial answer would be
+    /// wrong rather than incomplete.
+    pub fn is_equal(&self, v1: &Value, v2: &Value) -> bool {
+        self.equal_at(v1, v2, "")
+    }
+
+    /// Scores how alike `v1` and `v2` are, from 1.0 for documents with no
+    /// differences down to 0.0 when nothing matches.
+    ///
+    /// Every diff counts the nodes in the subtrees it carries, so replacing
+    /// a large object costs more than changing a single field. The score is
+    /// one minus the changed nodes over the nodes in both documents. With
+    /// [`JsonDiff::detect_renames`], a renamed key counts as one extra node
+    /// that changed, and the differences within its value count as usual.
+    /// Like [`JsonDiff::is_equal`], the limits do not apply.
+    pub fn similarity(&self, v1: &Value, v2: &Value) -> f64 {
+        self.score(v1, v2, "", Some(0), &Budget::unlimited(), true)
+    }
+
+    // Computes `similarity` for values found at `path` and `depth`, within
+    // `budget`. The score is meaningless once the budget is stopped.
+    fn score(
+        &self,
+        v1: &Value,
+        v2: &Value,
+        path: &str,
+        depth: Option<usize>,
+        budget: &Budget,
+        renames: bool,
+    ) -> f64 {
+        let mut total = count_nodes(v1) + count_nodes(v2);
+        let mut changed = 0;
+        let mut count = |diff: DiffRef| {
+            match diff.diff_type {
+                DiffType::Coerced(_) => {}
+                // The payloads of a `Moved` diff are the whole renamed value,
+                // which the diffs that follow it already account for.
+                DiffType::Moved => {
+                    total += 1;
+                    changed += 1;
+                }
+                _ => {
+                    changed += diff.old_value.map_or(0, count_nodes)
+                        + diff.new_value.map_or(0, count_nodes)
+                }
+            }
+            ControlFlow::Continue(())
+        };
+        let root = Frame::Compare {
+            v1,
+            v2,
+            path_len: path.len(),
+            depth,
+            hashes: None,
+        };
+        let _ = self.run(root, path, &mut count, budget, renames);
+        1.0 - changed as f64 / total as f64
+    }
+
+    /// Returns the differences between `v1` and `v2`. If a limit stops the
+    /// traversal early the result is partial; use [`JsonDiff::diff_report`]
+    /// to find out whether that happened.
     pub fn diff(&self, v1: &Value, v2: &Value) -> Vec<Diff> {
         let mut diffs = Vec::new();
-        self.diff_values(v1, v2, "", &mut diffs);
+        let _ = self.diff_with(v1, v2, &mut diffs);
+        diffs
+    }
+
+    /// Like [`JsonDiff::diff`], but also reports which limit, if any, cut the
+    /// traversal short.
+    pub fn diff_report(&self, v1: &Value, v2: &Value) -> DiffReport {
+        let budget = self.budget(v1, v2);
+        let mut diffs = Vec::new();
+        let _ = self.diff_limited(v1, root_frame(v1, v2), &mut diffs, &budget);
+        DiffReport {
+            diffs,
+            stopped_by: budget.stopped_by(),
+        }
+    }
+
+    /// Feeds each difference to `sink` as soon as it is found, without
+    /// collecting them. Returns `ControlFlow::Break` if the sink or a limit
+    /// stopped the traversal early.
+    pub fn diff_with<'a, S: DiffSink<'a>>(
+        &self,
+        v1: &'a Value,
+        v2: &'a Value,
+        sink: &mut S,
+    ) -> ControlFlow<()> {
+        self.diff_limited(v1, root_frame(v1, v2), sink, &self.budget(v1, v2))
+    }
+
+    /// Like [`JsonDiff::diff`], but subtrees whose cached hashes match are
+    /// skipped without being compared.
+    pub fn diff_prepared(&self, old: &PreparedDocument, new: &PreparedDocument) -> Vec<Diff> {
+        let (v1, v2) = (old.value(), new.value());
+        let root = Frame::Compare {
+            v1,
+            v2,
+            path_len: 0,
+            depth: Some(0),
+            hashes: Some((old.root(), new.root())),
+        };
+        let mut diffs = Vec::new();
+        let _ = self.diff_limited(v1, root, &mut diffs, &self.budget(v1, v2));
         diffs
     }
 
-    fn diff_values(&self, v1: &Value, v2: &Value, path: &str, diffs: &mut Vec<Diff>) {
+    /// Like [`JsonDiff::diff`], but the returned diffs borrow from `v1` and
+    /// `v2` instead of cloning the changed subtrees.
+    pub fn diff_ref<'a>(&self, v1: &'a Value, v2: &'a Value) -> Vec<DiffRef<'a>> {
+        let mut diffs = Vec::new();
+        let _ = self.diff_with(v1, v2, &mut diffs);
+        diffs
+    }
+
+    fn budget(&self, v1: &Value, v2: &Value) -> Budget {
+        self.budget_with_estimate(|| Some(count_nodes(v1).min(count_nodes(v2))))
+    }
+
+    // `estimated_total` is only computed when a progress observer is set.
+    pub(crate) fn budget_with_estimate(
+        &self,
+        estimated_total: impl FnOnce() -> Option<usize>,
+    ) -> Budget {
+        let budget = Budget::new(self.max_nodes, self.deadline, self.cancellation.clone());
+        match &self.progress {
+            Some(observer) => budget.with_progress(Arc::clone(observer), estimated_total()),
+            None => budget,
+        }
+    }
+
+    fn diff_limited<'a>(
+        &self,
+        old: &'a Value,
+        root: Frame<'a>,
+        sink: &mut dyn DiffSink<'a>,
+        budget: &Budget,
+    ) -> ControlFlow<()> {
+        let mut copies;
+        let sink: &mut dyn DiffSink<'a> = if self.detect_copies {
+            copies = CopySink {
+                inner: sink,
+                differ: self,
+                old,
+                sources: None,
+            };
+            &mut copies
+        } else {
+            sink
+        };
+
+        let flow = match self.max_diffs {
+            Some(remaining) => {
+                let mut limited = LimitedSink {
+                    inner: sink,
+                    remaining,
+                    budget,
+                };
+                self.run(root, "", &mut limited, budget, true)
+            }
+            None => self.run(root, "", sink, budget, true),
+        };
+        budget.finish();
+        flow
+    }
+
+    pub(crate) fn diff_values<'a>(
+        &self,
+        v1: &'a Value,
+        v2: &'a Value,
+        path: &str,
+        depth: usize,
+        sink: &mut dyn DiffSink<'a>,
+        budget: &Budget,
+    ) -> ControlFlow<()> {
+        let root = Frame::Compare {
+            v1,
+            v2,
+            path_len: path.len(),
+            depth: Some(depth),
+            hashes: None,
+        };
+        self.run(root, path, sink, budget, true)
+    }
+
+    // The traversal keeps its pending work on an explicit stack instead of
+    // recursing, so deeply nested input cannot overflow the call stack.
+    // Frames are pushed in reverse so diffs come out in document order.
+    //
+    // Equality checks needed while matching unordered arrays run as probes
+    // on the same stack: a `Probe` frame marks the start of the check, and
+    // the first diff emitted above it unwinds back to the marker and counts
+    // as "not equal". This keeps equality consistent with `diff`.
+    //
+    // Frames hold the length of their path rather than the path itself. The
+    // walk keeps one path buffer that each frame truncates to its own length
+    // before appending a child's segment, which works because the frames on
+    // the stack always lie along a single branch. `path` is the root's path.
+    //
+    // With `renames` off, objects are not searched for renamed keys even if
+    // `detect_renames` is set; scoring rename candidates runs this way.
+    fn run<'a>(
+        &self,
+        root: Frame<'a>,
+        path: &str,
+        sink: &mut dyn DiffSink<'a>,
+        budget: &Budget,
+        renames: bool,
+    ) -> ControlFlow<()> {
+        let mut walk = Walk {
+            stack: vec![root],
+            path: path.to_string(),
+            probes: 0,
+            probe_result: None,
+            budget,
+            renames,
+        };
+
+        while let Some(frame) = walk.stack.pop() {
+            // Diffs already found are still delivered after a limit is hit
+            // elsewhere, e.g. by another thread of a parallel diff.
+            if walk.budget.is_stopped() && !matches!(frame, Frame::Emit(_)) {
+                return ControlFlow::Break(());
+            }
+
+            match frame {
+                Frame::Compare {
+                    v1,
+                    v2,
+                    path_len,
+                    depth,
+                    hashes,
+                } => {
+                    walk.budget.visit()?;
+                    self.compare(v1, v2, path_len, depth, hashes, &mut walk);
+                }
+                Frame::Items {
+                    a1,
+                    a2,
+                    path_len,
+                    depth,
+                    hashes,
+                    index,
+                } => {
+                    if index < a1.len().max(a2.len()) {
+                        let child_len = walk.enter_index(path_len, index);
+                        let child_hashes = item_hashes(hashes, index, index);
+                        let child = child_frame(
+                            &walk.path[..child_len],
+                            a1.get(index),
+                            a2.get(index),
+                            depth,
+                            child_hashes,
+                        );
+                        walk.stack.push(Frame::Items {
+                            a1,
+                            a2,
+                            path_len,
+                            depth,
+                            hashes,
+                            index: index + 1,
+                        });
+                        walk.stack.extend(child);
+                    }
+                }
+                Frame::Members {
+                    o1,
+                    o2,
+                    path_len,
+                    depth,
+                    hashes,
+                    mut keys,
+                    new_keys,
+                    renames,
+                } => {
+                    let next = if new_keys {
+                        keys.find(|key| !o1.contains_key(*key))
+                    } else {
+                        keys.find(|key| !renames.iter().any(|(old, _)| old == key))
+                    };
+                    match next {
+                        Some(key) => {
+                            let child_len = walk.enter_key(path_len, key);
+                            let child_path = &walk.path[..child_len];
+                            let renamed_from = renames
+                                .iter()
+                                .find(|(_, new)| new == key)
+                                .map(|(old, _)| *old);
+                            let children = match renamed_from {
+                                Some(old_key) => {
+                                    let old_value = &o1[old_key];
+                                    let new_value = &o2[key];
+                                    let child_hashes = hashes.and_then(|(h1, h2)| {
+                                        Some((h1.member(old_key)?, h2.member(key)?))
+                                    });
+                                    vec![
+                                        Frame::Compare {
+                                            v1: old_value,
+                                            v2: new_value,
+                                            path_len: child_len,
+                                            depth,
+                                            hashes: child_hashes,
+                                        },
+                                        Frame::Emit(DiffRef {
+                                            path: child_path.to_string(),
+                                            from_path: Some(key_path(
+                                                &walk.path[..path_len],
+                                                old_key,
+                                            )),
+                                            diff_type: DiffType::Moved,
+                                            old_value: Some(old_value),
+                                            new_value: Some(new_value),
+                                        }),
+                                    ]
+                                }
+                                None if self.member_implied(
+                                    o1.get(key),
+                                    o2.get(key),
+                                    child_path,
+                                ) =>
+                                {
+                                    Vec::new()
+                                }
+                                None => {
+                                    let child_hashes = member_hashes(hashes, key);
+                                    child_frame(
+                                        child_path,
+                                        o1.get(key),
+                                        o2.get(key),
+                                        depth,
+                                        child_hashes,
+                                    )
+                                    .into_iter()
+                                    .collect()
+                                }
+                            };
+                            walk.stack.push(Frame::Members {
+                                o1,
+                                o2,
+                                path_len,
+                                depth,
+                                hashes,
+                                keys,
+                                new_keys,
+                                renames,
+                            });
+                            walk.stack.extend(children);
+                        }
+                        None if !new_keys => walk.stack.push(Frame::Members {
+                            o1,
+                            o2,
+                            path_len,
+                            depth,
+                            hashes,
+                            keys: o2.keys(),
+                            new_keys: true,
+                            renames,
+                        }),
+                        None => {}
+                    }
+                }
+                Frame::Match(state) => self.match_next(state, &mut walk),
+                Frame::Truncate { v1, v2, path_len } => {
+                    if walk.probe_result.take() == Some(false) {
+                        walk.stack.push(Frame::Emit(DiffRef {
+                            path: walk.path[..path_len].to_string(),
+                            from_path: None,
+                            diff_type: DiffType::Truncated,
+                            old_value: Some(v1),
+                            new_value: Some(v2),
+                        }));
+                    }
+                }
+                Frame::Probe => {
+                    walk.probes -= 1;
+                    walk.probe_result = Some(true);
+                }
+                Frame::Emit(_) if walk.probes > 0 => {
+                    while let Some(frame) = walk.stack.pop() {
+                        if let Frame::Probe = frame {
+                            break;
+                        }
+                    }
+                    walk.probes -= 1;
+                    walk.probe_result = Some(false);
+                }
+                Frame::Emit(diff) => sink.on_diff(diff)?,
+            }
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    fn compare<'a>(
+        &self,
+        v1: &'a Value,
+        v2: &'a Value,
+        path_len: usize,
+        depth: Option<usize>,
+        hashes: Hashes<'a>,
+        walk: &mut Walk<'a, '_>,
+    ) {
+        if let Some((h1, h2)) = hashes {
+            if h1.hash == h2.hash {
+                return;
+            }
+        }
+
+        let path = &walk.path[..path_len];
+        match self.custom_comparison(v1, v2, path) {
+            Comparison::Equal => return,
+            Comparison::Different => {
+                walk.stack.push(Frame::Emit(DiffRef {
+                    path: path.to_string(),
+                    from_path: None,
+                    diff_type: modification(v1, v2),
+                    old_value: Some(v1),
+                    new_value: Some(v2),
+                }));
+                return;
+            }
+            Comparison::Defer => {}
+        }
+
+        let at_max_depth = match (depth, self.max_depth) {
+            (Some(depth), Some(max_depth)) => depth >= max_depth,
+            _ => false,
+        };
+        let child_depth = depth.map(|depth| depth + 1);
+
         match (v1, v2) {
             (Value::Null, Value::Null) => {}
             (Value::Bool(b1), Value::Bool(b2)) if b1 == b2 => {}
-            (Value::Number(n1), Value::Number(n2)) if n1 == n2 => {}
+            (Value::Number(n1), Value::Number(n2)) if numbers_equal(n1, n2) => {}
             (Value::String(s1), Value::String(s2)) if s1 == s2 => {}
+            (Value::Array(_), Value::Array(_)) | (Value::Object(_), Value::Object(_))
+                if at_max_depth =>
+            {
+                walk.stack.push(Frame::Truncate { v1, v2, path_len });
+                walk.probe(v1, v2, path_len, None, hashes);
+            }
+            (Value::Array(a1), Value::Array(a2)) if self.ignore_order => {
+                walk.stack.push(Frame::Match(Box::new(MatchState {
+                    a1,
+                    a2,
+                    path_len,
+                    depth: child_depth,
+                    hashes,
+                    used: vec![false; a2.len()],
+                    unmatched: Vec::new(),
+                    i: 0,
+                    j: 0,
+                })));
+            }
             (Value::Array(a1), Value::Array(a2)) => {
-                self.diff_arrays(a1, a2, path, diffs);
+                #[cfg(feature = "parallel")]
+                if walk.probes == 0 && a1.len().max(a2.len()) >= self.parallel_threshold {
+                    let children = (0..a1.len().max(a2.len()))
+                        .map(|i| {
+                            let child_hashes = item_hashes(hashes, i, i);
+                            (index_path(path, i), a1.get(i), a2.get(i), child_hashes)
+                        })
+                        .collect();
+                    return self.diff_children_parallel(children, child_depth, walk);
+                }
+
+                walk.stack.push(Frame::Items {
+                    a1,
+                    a2,
+                    path_len,
+                    depth: child_depth,
+                    hashes,
+                    index: 0,
+                });
             }
             (Value::Object(o1), Value::Object(o2)) => {
-                self.diff_objects(o1, o2, path, diffs);
+                // A probe only asks whether the objects are equal, and a
+                // rename is a difference either way.
+                let renames = match self.rename_threshold {
+                    Some(threshold) if walk.probes == 0 && walk.renames => {
+                        find_renames(o1, o2, path, child_depth, threshold, self, walk.budget)
+                    }
+                    _ => Vec::new(),
+                };
+
+                #[cfg(feature = "parallel")]
+                if walk.probes == 0
+                    && renames.is_empty()
+                    && o1.len().max(o2.len()) >= self.parallel_threshold
+                {
+                    let children = o1
+                        .keys()
+                        .chain(o2.keys().filter(|k| !o1.contains_key(*k)))
+                        .map(|key| {
+                            let child_hashes = member_hashes(hashes, key);
+                            (key_path(path, key), o1.get(key), o2.get(key), child_hashes)
+                        })
+                        .filter(|(path, v1, v2, _)| !self.member_implied(*v1, *v2, path))
+                        .collect();
+                    return self.diff_children_parallel(children, child_depth, walk);
+                }
+
+                // Keys are visited in the old object's order followed by keys
+                // that only exist in the new object, so the order of reported
+                // diffs is stable.
+                walk.stack.push(Frame::Members {
+                    o1,
+                    o2,
+                    path_len,
+                    depth: child_depth,
+                    hashes,
+                    keys: o1.keys(),
+                    new_keys: false,
+                    renames,
+                });
             }
+            _ if self.counts_as_null(v1, path) && self.counts_as_null(v2, path) => {}
             _ => {
-                diffs.push(Diff {
+                let diff_type = match self.coercion(v1, v2, path) {
+                    // A probe only asks whether the values are equal.
+                    Some(_) if !self.report_coercions || walk.probes > 0 => return,
+                    Some(coercion) => DiffType::Coerced(coercion),
+                    None => modification(v1, v2),
+                };
+                walk.stack.push(Frame::Emit(DiffRef {
                     path: path.to_string(),
-                    diff_type: DiffType::Modified,
-                    old_value: Some(v1.clone()),
-                    new_value: Some(v2.clone()),
-                });
+                    from_path: None,
+                    diff_type,
+                    old_value: Some(v1),
+                    new_value: Some(v2),
+                }));
             }
         }
     }
 
-    fn diff_arrays(&self, a1: &[Value], a2: &[Value], path: &str, diffs: &mut Vec<Diff>) {
-        if self.ignore_order {
-            self.diff_arrays_ignore_order(a1, a2, path, diffs);
-        } else {
-            self.diff_arrays_preserve_order(a1, a2, path, diffs);
+    // Asks each registered comparator that applies, in order, until one has
+    // an opinion.
+    fn custom_comparison(&self, v1: &Value, v2: &Value, path: &str) -> Comparison {
+        let types = (JsonType::of(v1), JsonType::of(v2));
+        self.comparators
+            .iter()
+            .filter(|(target, _)| target.applies(path, types))
+            .map(|(_, comparator)| comparator.compare(path, v1, v2))
+            .find(|comparison| *comparison != Comparison::Defer)
+            .unwrap_or(Comparison::Defer)
+    }
+
+    /// Whether a registered comparator applies to values of these types at
+    /// `path`, so they must be compared as a whole.
+    pub(crate) fn has_comparator(&self, path: &str, old: JsonType, new: JsonType) -> bool {
+        self.comparators
+            .iter()
+            .any(|(target, _)| target.applies(path, (old, new)))
+    }
+
+    // Whether values are equal exactly when they are structurally identical,
+    // as under the default options.
+    fn equality_is_structural(&self) -> bool {
+        !self.ignore_order
+            && self.coercions.is_empty()
+            && self.equivalences.is_empty()
+            && self.defaults.is_empty()
+            && self.path_defaults.is_empty()
+            && self.comparators.is_empty()
+    }
+
+    fn equal_at(&self, v1: &Value, v2: &Value, path: &str) -> bool {
+        let mut differs = |diff: DiffRef| match diff.diff_type {
+            DiffType::Coerced(_) => ControlFlow::Continue(()),
+            _ => ControlFlow::Break(()),
+        };
+        self.diff_values(v1, v2, path, 0, &mut differs, &Budget::unlimited())
+            .is_continue()
+    }
+
+    fn equivalence_applies(&self, equivalence: Equivalence, path: &str) -> bool {
+        self.equivalences.iter().any(|(pattern, enabled)| {
+            *enabled == equivalence && pattern.as_ref().is_none_or(|p| p.matches(path))
+        })
+    }
+
+    fn counts_as_null(&self, value: &Value, path: &str) -> bool {
+        match value {
+            Value::Null => true,
+            Value::Array(items) => {
+                items.is_empty() && self.equivalence_applies(Equivalence::EmptyIsNull, path)
+            }
+            Value::Object(map) => {
+                map.is_empty() && self.equivalence_applies(Equivalence::EmptyIsNull, path)
+            }
+            _ => false,
         }
     }
 
-    fn diff_arrays_preserve_order(
+    // Whether an object member present on only one side, at `path`, is
+    // implied on the other: it counts as missing under the equivalences, or
+    // it equals the default the missing side is assumed to hold.
+    pub(crate) fn member_implied(
         &self,
-        a1: &[Value],
-        a2: &[Value],
+        v1: Option<&Value>,
+        v2: Option<&Value>,
         path: &str,
-        diffs: &mut Vec<Diff>,
-    ) {
-        let max_len = a1.len().max(a2.len());
+    ) -> bool {
+        if self.equivalences.is_empty() && self.defaults.is_empty() && self.path_defaults.is_empty()
+        {
+            return false;
+        }
+        let value = match (v1, v2) {
+            (Some(value), None) | (None, Some(value)) => value,
+            _ => return false,
+        };
+        if self.equivalence_applies(Equivalence::NullIsMissing, path)
+            && self.counts_as_null(value, path)
+        {
+            return true;
+        }
+        self.default_for(path)
+            .is_some_and(|default| self.equal_at(value, default, path))
+    }
 
-        for i in 0..max_len {
-            let new_path = if path.is_empty() {
-                format!("[{}]", i)
-            } else {
-                format!("{}[{}]", path, i)
-            };
+    fn default_for(&self, path: &str) -> Option<&Value> {
+        self.defaults.get(path).or_else(|| {
+            self.path_defaults
+                .iter()
+                .find(|(pattern, _)| pattern.matches(path))
+                .map(|(_, value)| value)
+        })
+    }
 
-            match (a1.get(i), a2.get(i)) {
-                (Some(v1), Some(v2)) => {
-                    self.diff_values(v1, v2, &new_path, diffs);
-                }
-                (Some(v1), None) => {
-                    diffs.push(Diff {
-                        path: new_path,
-                        diff_type: DiffType::Removed,
-                        old_value: Some(v1.clone()),
-                        new_value: None,
-                    });
+    // Returns the first enabled coercion that applies at `path` and makes
+    // the values equal.
+    fn coercion(&self, v1: &Value, v2: &Value, path: &str) -> Option<Coercion> {
+        if JsonType::of(v1) == JsonType::of(v2) {
+            return None;
+        }
+        self.coercions
+            .iter()
+            .filter(|(pattern, _)| pattern.as_ref().is_none_or(|p| p.matches(path)))
+            .map(|(_, coercion)| *coercion)
+            .find(|coercion| coercion.equal(v1, v2))
+    }
+
+    // Greedily pairs each element of `a1` with the first equal, still unused
+    // element of `a2`. Each candidate pair is checked with a probe; the
+    // outcome arrives in `walk.probe_result` the next time this frame runs.
+    fn match_next<'a>(&self, mut state: Box<MatchState<'a>>, walk: &mut Walk<'a, '_>) {
+        match walk.probe_result.take() {
+            Some(true) => {
+                state.used[state.j] = true;
+                state.i += 1;
+                state.j = 0;
+            }
+            Some(false) => state.j += 1,
+            None => {}
+        }
+
+        while state.i < state.a1.len() {
+            match (state.j..state.a2.len()).find(|&j| !state.used[j]) {
+                Some(j) => {
+                    state.j = j;
+                    let v1 = &state.a1[state.i];
+                    let v2 = &state.a2[j];
+                    let path_len = walk.enter_index(state.path_len, state.i);
+                    let depth = state.depth;
+                    let hashes = item_hashes(state.hashes, state.i, j);
+                    walk.stack.push(Frame::Match(state));
+                    walk.probe(v1, v2, path_len, depth, hashes);
+                    return;
                 }
-                (None, Some(v2)) => {
-                    diffs.push(Diff {
-                        path: new_path,
-                        diff_type: DiffType::Added,
-                        old_value: None,
-                        new_value: Some(v2.clone()),
-                    });
+                None => {
+                    state.unmatched.push(state.i);
+                    state.i += 1;
+                    state.j = 0;
                 }
-                (None, None) => {}
             }
         }
+
+        let path = &walk.path[..state.path_len];
+        let added = (0..state.a2.len()).filter(|&j| !state.used[j]).rev();
+        for j in added {
+            walk.stack.push(Frame::Emit(DiffRef {
+                path: index_path(path, j),
+                from_path: None,
+                diff_type: DiffType::Added,
+                old_value: None,
+                new_value: Some(&state.a2[j]),
+            }));
+        }
+        for &i in state.unmatched.iter().rev() {
+            walk.stack.push(Frame::Emit(DiffRef {
+                path: index_path(path, i),
+                from_path: None,
+                diff_type: DiffType::Removed,
+                old_value: Some(&state.a1[i]),
+                new_value: None,
+            }));
+        }
     }
 
-    fn diff_arrays_ignore_order(
+    // Each child is diffed on the rayon pool into its own buffer, and the
+    // buffered diffs are pushed back in child order so the output matches a
+    // sequential run. If a limit stops some child early, children after it
+    // are dropped so the partial output is still a prefix of the full one.
+    #[cfg(feature = "parallel")]
+    fn diff_children_parallel<'a>(
         &self,
-        a1: &[Value],
-        a2: &[Value],
-        path: &str,
-        diffs: &mut Vec<Diff>,
+        children: Vec<(String, Option<&'a Value>, Option<&'a Value>, Hashes<'a>)>,
+        depth: Option<usize>,
+        walk: &mut Walk<'a, '_>,
     ) {
-        let mut unused1: Vec<bool> = vec![false; a1.len()];
-        let mut unused2: Vec<bool> = vec![false; a2.len()];
-
-        for (i, v1) in a1.iter().enumerate() {
-            let mut found = false;
-            for (j, v2) in a2.iter().enumerate() {
-                if !unused2[j] && self.values_equal(v1, v2) {
-                    unused1[i] = true;
-                    unused2[j] = true;
-                    found = true;
-                    break;
-                }
-            }
+        use rayon::prelude::*;
 
-            if !found {
-                let new_path = if path.is_empty() {
-                    format!("[{}]", i)
-                } else {
-                    format!("{}[{}]", path, i)
+        let (budget, renames) = (walk.budget, walk.renames);
+        let results: Vec<(Vec<DiffRef<'a>>, ControlFlow<()>)> = children
+            .into_par_iter()
+            .map(|(path, v1, v2, hashes)| {
+                let mut diffs = Vec::new();
+                let flow = match child_frame(&path, v1, v2, depth, hashes) {
+                    Some(frame) => self.run(frame, &path, &mut diffs, budget, renames),
+                    None => ControlFlow::Continue(()),
                 };
+                (diffs, flow)
+            })
+            .collect();
 
-                diffs.push(Diff {
-                    path: new_path,
-                    diff_type: DiffType::Removed,
-                    old_value: Some(v1.clone()),
-                    new_value: None,
-                });
-            }
-        }
-
-        for (j, v2) in a2.iter().enumerate() {
-            if !unused2[j] {
-                let new_path = if path.is_empty() {
-                    format!("[{}]", j)
-                } else {
-                    format!("{}[{}]", path, j)
-                };
+        let complete = results
+            .iter()
+            .position(|(_, flow)| flow.is_break())
+            .map_or(results.len(), |stopped| stopped + 1);
+        let diffs: Vec<_> = results
+            .into_iter()
+            .take(complete)
+            .flat_map(|(diffs, _)| diffs)
+            .collect();
+        walk.stack.extend(diffs.into_iter().rev().map(Frame::Emit));
+    }
+}
 
-                diffs.push(Diff {
-                    path: new_path,
-                    diff_type: DiffType::Added,
-                    old_value: None,
-                    new_value: Some(v2.clone()),
-                });
-            }
+impl ComparatorTarget {
+    fn applies(&self, path: &str, (old, new): (JsonType, JsonType)) -> bool {
+        match self {
+            ComparatorTarget::Path(pattern) => pattern.matches(path),
+            ComparatorTarget::Type(json_type) => old == *json_type && new == *json_type,
         }
     }
+}
 
-    fn diff_objects(
-        &self,
-        o1: &serde_json::Map<String, Value>,
-        o2: &serde_json::Map<String, Value>,
-        path: &str,
-        diffs: &mut Vec<Diff>,
+// The cached subtree hashes of both sides, when diffing prepared documents.
+type Hashes<'a> = Option<(&'a HashNode, &'a HashNode)>;
+
+enum Frame<'a> {
+    Compare {
+        v1: &'a Value,
+        v2: &'a Value,
+        path_len: usize,
+        depth: Option<usize>,
+        hashes: Hashes<'a>,
+    },
+    Items {
+        a1: &'a [Value],
+        a2: &'a [Value],
+        path_len: usize,
+        depth: Option<usize>,
+        hashes: Hashes<'a>,
+        index: usize,
+    },
+    Members {
+        o1: &'a Map<String, Value>,
+        o2: &'a Map<String, Value>,
+        path_len: usize,
+        depth: Option<usize>,
+        hashes: Hashes<'a>,
+        keys: serde_json::map::Keys<'a>,
+        new_keys: bool,
+        // (old key, new key) pairs found by rename detection.
+        renames: Vec<(&'a str, &'a str)>,
+    },
+    Match(Box<MatchState<'a>>),
+    Truncate {
+        v1: &'a Value,
+        v2: &'a Value,
+        path_len: usize,
+    },
+    Probe,
+    Emit(DiffRef<'a>),
+}
+
+struct MatchState<'a> {
+    a1: &'a [Value],
+    a2: &'a [Value],
+    path_len: usize,
+    depth: Option<usize>,
+    hashes: Hashes<'a>,
+    used: Vec<bool>,
+    unmatched: Vec<usize>,
+    i: usize,
+    j: usize,
+}
+
+struct Walk<'a, 'b> {
+    stack: Vec<Frame<'a>>,
+    // The path of the innermost frame, a prefix of which is the path of
+    // every other frame on the stack.
+    path: String,
+    probes: usize,
+    probe_result: Option<bool>,
+    budget: &'b Budget,
+    renames: bool,
+}
+
+impl<'a> Walk<'a, '_> {
+    fn probe(
+        &mut self,
+        v1: &'a Value,
+        v2: &'a Value,
+        path_len: usize,
+        depth: Option<usize>,
+        hashes: Hashes<'a>,
     ) {
-        let all_keys: std::collections::HashSet<&String> = o1.keys().chain(o2.keys()).collect();
+        self.probes += 1;
+        self.stack.push(Frame::Probe);
+        self.stack.push(Frame::Compare {
+            v1,
+            v2,
+            path_len,
+            depth,
+            hashes,
+        });
+    }
 
-        for key in all_keys {
-            let new_path = if path.is_empty() {
-                key.clone()
-            } else {
-                format!("{}.{}", path, key)
-            };
+    // Replaces whatever follows the path of length `path_len` in the buffer
+    // with the path of its item at `index`, returning the new length.
+    fn enter_index(&mut self, path_len: usize, index: usize) -> usize {
+        self.path.truncate(path_len);
+        push_index(&mut self.path, index);
+        self.path.len()
+    }
 
-            match (o1.get(key), o2.get(key)) {
-                (Some(v1), Some(v2)) => {
-                    self.diff_values(v1, v2, &new_path, diffs);
-                }
-                (Some(v1), None) => {
-                    diffs.push(Diff {
-                        path: new_path,
-                        diff_type: DiffType::Removed,
-                        old_value: Some(v1.clone()),
-                        new_value: None,
-                    });
+    // Like `enter_index`, for the member at `key`.
+    fn enter_key(&mut self, path_len: usize, key: &str) -> usize {
+        self.path.truncate(path_len);
+        push_key(&mut self.path, key);
+        self.path.len()
+    }
+}
+
+fn root_frame<'a>(v1: &'a Value, v2: &'a Value) -> Frame<'a> {
+    Frame::Compare {
+        v1,
+        v2,
+        path_len: 0,
+        depth: Some(0),
+        hashes: None,
+    }
+}
+
+// `path` must be what the walk's path buffer holds, up to its length.
+fn child_frame<'a>(
+    path: &str,
+    v1: Option<&'a Value>,
+    v2: Option<&'a Value>,
+    depth: Option<usize>,
+    hashes: Hashes<'a>,
+) -> Option<Frame<'a>> {
+    match (v1, v2) {
+        (Some(v1), Some(v2)) => Some(Frame::Compare {
+            v1,
+            v2,
+            path_len: path.len(),
+            depth,
+            hashes,
+        }),
+        (Some(v1), None) => Some(Frame::Emit(DiffRef {
+            path: path.to_string(),
+            from_path: None,
+            diff_type: DiffType::Removed,
+            old_value: Some(v1),
+            new_value: None,
+        })),
+        (None, Some(v2)) => Some(Frame::Emit(DiffRef {
+            path: path.to_string(),
+            from_path: None,
+            diff_type: DiffType::Added,
+            old_value: None,
+            new_value: Some(v2),
+        })),
+        (None, None) => None,
+    }
+}
+
+/// Classifies a change from `v1` to `v2` as `Modified`, or as `TypeChanged`
+/// if the two values have different JSON types.
+pub(crate) fn modification(v1: &Value, v2: &Value) -> DiffType {
+    let (old, new) = (JsonType::of(v1), JsonType::of(v2));
+    if old == new {
+        DiffType::Modified
+    } else {
+        DiffType::TypeChanged { old, new }
+    }
+}
+
+// `Value::clone` recurses once per nesting level, so the subtrees carried by
+// owned diffs are copied with an explicit stack instead.
+pub(crate) fn clone_value(value: &Value) -> Value {
+    enum Open<'v> {
+        Array(std::slice::Iter<'v, Value>, Vec<Value>),
+        Object(serde_json::map::Iter<'v>, Map<String, Value>, String),
+    }
+
+    let mut stack = Vec::new();
+    let mut current = value;
+    loop {
+        let mut completed = match current {
+            Value::Array(items) => {
+                stack.push(Open::Array(items.iter(), Vec::with_capacity(items.len())));
+                None
+            }
+            Value::Object(map) => {
+                stack.push(Open::Object(map.iter(), Map::new(), String::new()));
+                None
+            }
+            scalar => Some(scalar.clone()),
+        };
+
+        // Hand finished values to their parents until one has another child
+        // left to copy.
+        loop {
+            match stack.last_mut() {
+                None => return completed.unwrap_or(Value::Null),
+                Some(Open::Array(iter, items)) => {
+                    items.extend(completed.take());
+                    if let Some(item) = iter.next() {
+                        current = item;
+                        break;
+                    }
                 }
-                (None, Some(v2)) => {
-                    diffs.push(Diff {
-                        path: new_path,
-                        diff_type: DiffType::Added,
-                        old_value: None,
-                        new_value: Some(v2.clone()),
-                    });
+                Some(Open::Object(iter, map, key)) => {
+                    if let Some(item) = completed.take() {
+                        map.insert(std::mem::take(key), item);
+                    }
+                    if let Some((next_key, item)) = iter.next() {
+                        key.clone_from(next_key);
+                        current = item;
+                        break;
+                    }
                 }
-                (None, None) => {}
             }
+            completed = match stack.pop() {
+                Some(Open::Array(_, items)) => Some(Value::Array(items)),
+                Some(Open::Object(_, map, _)) => Some(Value::Object(map)),
+                None => None,
+            };
         }
     }
+}
 
-    fn values_equal(&self, v1: &Value, v2: &Value) -> bool {
-        match (v1, v2) {
-            (Value::Null, Value::Null) => true,
-            (Value::Bool(b1), Value::Bool(b2)) => b1 == b2,
-            (Value::Number(n1), Value::Number(n2)) => n1 == n2,
-            (Value::String(s1), Value::String(s2)) => s1 == s2,
-            (Value::Array(a1), Value::Array(a2)) => {
-                if a1.len() != a2.len() {
-                    return false;
-                }
-                if self.ignore_order {
-                    self.arrays_equal_ignore_order(a1, a2)
-                } else {
-                    a1.iter()
-                        .zip(a2.iter())
-                        .all(|(v1, v2)| self.values_equal(v1, v2))
+fn is_nonempty_container(value: &Value) -> bool {
+    match value {
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+        _ => false,
+    }
+}
+
+// Indexes every non-empty container in `old` by its subtree hash, in
+// document order. Unless equality is `structural`, equal values may hash
+// differently, so every container goes under the key 0 instead.
+fn copy_sources(old: &Value, structural: bool) -> HashMap<u64, Vec<(String, &Value)>> {
+    let root = HashNode::build(old);
+    let mut sources: HashMap<u64, Vec<(String, &Value)>> = HashMap::new();
+    let mut stack = vec![(String::new(), old, &root)];
+    while let Some((path, value, node)) = stack.pop() {
+        match value {
+            Value::Array(items) => {
+                for (i, item) in items.iter().enumerate().rev() {
+                    if let Some(child) = node.item(i) {
+                        stack.push((index_path(&path, i), item, child));
+                    }
                 }
             }
-            (Value::Object(o1), Value::Object(o2)) => {
-                if o1.len() != o2.len() {
-                    return false;
+            Value::Object(map) => {
+                for (key, item) in map.iter().rev() {
+                    if let Some(child) = node.member(key) {
+                        stack.push((key_path(&path, key), item, child));
+                    }
                 }
-                o1.keys().all(|k| match (o1.get(k), o2.get(k)) {
-                    (Some(v1), Some(v2)) => self.values_equal(v1, v2),
-                    _ => false,
-                })
             }
-            _ => false,
+            _ => continue,
         }
-    }
-
-    fn arrays_equal_ignore_order(&self, a1: &[Value], a2: &[Value]) -> bool {
-        if a1.len() != a2.len() {
-            return false;
+        if is_nonempty_container(value) {
+            let key = if structural { node.hash } else { 0 };
+            sources.entry(key).or_default().push((path, value));
         }
+    }
+    sources
+}
 
-        let mut used = vec![false; a2.len()];
-
-        for v1 in a1 {
-            let mut found = false;
-            for (j, v2) in a2.iter().enumerate() {
-                if !used[j] && self.values_equal(v1, v2) {
-                    used[j] = true;
-                    found = true;
-                    break;
-                }
+// Pairs each key only `o1` has with the most alike unpaired key only `o2`
+// has, scoring at least `threshold`. Values are scored with all of the
+// differ's options, under the new key's path, except that renames nested
+// inside them are ignored, so pairing never recurses. Members that count as
+// missing under the differ's equivalences are not candidates. Scoring counts
+// against `budget`, and pairing ends as soon as a limit stops it.
+fn find_renames<'a>(
+    o1: &'a Map<String, Value>,
+    o2: &'a Map<String, Value>,
+    path: &str,
+    depth: Option<usize>,
+    threshold: f64,
+    differ: &JsonDiff,
+    budget: &Budget,
+) -> Vec<(&'a str, &'a str)> {
+    let mut added: Vec<&str> = o2
+        .iter()
+        .filter(|(key, v2)| {
+            !o1.contains_key(*key) && !differ.member_implied(None, Some(v2), &key_path(path, key))
+        })
+        .map(|(key, _)| key.as_str())
+        .collect();
+    let mut renames = Vec::new();
+    let removed = o1.iter().filter(|(key, v1)| {
+        !o2.contains_key(*key) && !differ.member_implied(Some(v1), None, &key_path(path, key))
+    });
+    for (old_key, v1) in removed {
+        let mut best: Option<(usize, f64)> = None;
+        for (i, new_key) in added.iter().enumerate() {
+            let new_path = key_path(path, new_key);
+            let score = differ.score(v1, &o2[*new_key], &new_path, depth, budget, false);
+            if budget.is_stopped() {
+                return renames;
             }
-            if !found {
-                return false;
+            if score >= threshold && best.is_none_or(|(_, best)| score > best) {
+                best = Some((i, score));
             }
         }
+        if let Some((i, _)) = best {
+            renames.push((old_key.as_str(), added.remove(i)));
+        }
+    }
+    renames
+}
 
-        true
+fn item_hashes(hashes: Hashes<'_>, i: usize, j: usize) -> Hashes<'_> {
+    let (h1, h2) = hashes?;
+    Some((h1.item(i)?, h2.item(j)?))
+}
+
+fn member_hashes<'a>(hashes: Hashes<'a>, key: &str) -> Hashes<'a> {
+    let (h1, h2) = hashes?;
+    Some((h1.member(key)?, h2.member(key)?))
+}
+
+pub(crate) fn index_path(path: &str, index: usize) -> String {
+    let mut path = path.to_string();
+    push_index(&mut path, index);
+    path
+}
+
+pub(crate) fn key_path(path: &str, key: &str) -> String {
+    let mut path = path.to_string();
+    push_key(&mut path, key);
+    path
+}
+
+/// Extends `path` to the path of its item at `index`.
+pub(crate) fn push_index(path: &mut String, index: usize) {
+    let _ = write!(path, "[{}]", index);
+}
+
+/// Extends `path` to the path of its member at `key`.
+pub(crate) fn push_key(path: &mut String, key: &str) {
+    if !path.is_empty() {
+        path.push('.');
     }
+    path.push_str(key);
 }
 
 impl Default for JsonDiff {
@@ -295,3 +1583,107 @@ impl Default for JsonDiff {
         Self::new()
     }
 }
+
+#[cfg(not(feature = "arbitrary_precision"))]
+pub(crate) fn numbers_equal(n1: &Number, n2: &Number) -> bool {
+    n1 == n2
+}
+
+/// Returns a key that is equal for two numbers exactly when
+/// `numbers_equal` holds, so numbers can be hashed consistently with how
+/// they are compared.
+#[cfg(not(feature = "arbitrary_precision"))]
+pub(crate) fn number_key(n: &Number) -> String {
+    match (n.as_u64(), n.as_i64()) {
+        _ if n.is_f64() => {
+            // -0.0 and 0.0 compare equal, so they must share a key.
+            let f = n.as_f64().unwrap_or_default();
+            let f = if f == 0.0 { 0.0 } else { f };
+            format!("f{:016x}", f.to_bits())
+        }
+        (Some(u), _) => format!("u{}", u),
+        (None, Some(i)) => format!("i{}", i),
+        (None, None) => n.to_string(),
+    }
+}
+
+#[cfg(feature = "arbitrary_precision")]
+pub(crate) fn number_key(n: &Number) -> String {
+    match Decimal::parse(n.as_str()) {
+        Some(d) => format!(
+            "{}{}e{}",
+            if d.negative { "-" } else { "" },
+            d.digits,
+            d.exponent
+        ),
+        None => format!("text:{}", n.as_str()),
+    }
+}
+
+/// With `arbitrary_precision` numbers keep their source text, so `1.50` and
+/// `1.5e0` compare equal by decimal value instead of by spelling.
+#[cfg(feature = "arbitrary_precision")]
+pub(crate) fn numbers_equal(n1: &Number, n2: &Number) -> bool {
+    match (Decimal::parse(n1.as_str()), Decimal::parse(n2.as_str())) {
+        (Some(d1), Some(d2)) => d1 == d2,
+        _ => n1.as_str() == n2.as_str(),
+    }
+}
+
+#[cfg(feature = "arbitrary_precision")]
+#[derive(Debug, PartialEq)]
+pub(crate) struct Decimal {
+    negative: bool,
+    digits: String,
+    exponent: i64,
+}
+
+#[cfg(feature = "arbitrary_precision")]
+impl Decimal {
+    /// Normalizes a JSON number literal to `digits * 10^exponent` with no
+    /// leading or trailing zeros in `digits`. Zero is always non-negative.
+    pub(crate) fn parse(text: &str) -> Option<Decimal> {
+        let (negative, rest) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (mantissa, exponent) = match rest.find(['e', 'E']) {
+            Some(pos) => (&rest[..pos], rest[pos + 1..].parse::<i64>().ok()?),
+            None => (rest, 0),
+        };
+        let (int_part, frac_part) = match mantissa.find('.') {
+            Some(pos) => (&mantissa[..pos], &mantissa[pos + 1..]),
+            None => (mantissa, ""),
+        };
+        if int_part.is_empty()
+            || !int_part
+                .bytes()
+                .chain(frac_part.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+
+        let all_digits = format!("{}{}", int_part, frac_part);
+        let trimmed_start = all_digits.trim_start_matches('0');
+        let digits = trimmed_start.trim_end_matches('0');
+        if digits.is_empty() {
+            return Some(Decimal {
+                negative: false,
+                digits: String::new(),
+                exponent: 0,
+            });
+        }
+
+        let trailing_zeros = (trimmed_start.len() - digits.len()) as i64;
+        let exponent = exponent
+            .checked_sub(frac_part.len() as i64)?
+            .checked_add(trailing_zeros)?;
+
+        Some(Decimal {
+            negative,
+            digits: digits.to_string(),
+            exponent,
+        })
+    }
+}
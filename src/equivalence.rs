@@ -0,0 +1,17 @@
+/// A rule under which null, empty and missing values compare equal,
+/// enabled with [`JsonDiff::equivalence`] or [`JsonDiff::equivalence_at`].
+///
+/// The rules combine: with both enabled, `{"a": []}`, `{"a": {}}`,
+/// `{"a": null}` and `{}` are all equal. Paths are matched against the
+/// member or value being compared, e.g. `a` in the examples above.
+///
+/// [`JsonDiff::equivalence`]: crate::JsonDiff::equivalence
+/// [`JsonDiff::equivalence_at`]: crate::JsonDiff::equivalence_at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Equivalence {
+    /// An object member whose value is `null` equals a missing member.
+    NullIsMissing,
+    /// An empty array or object equals `null`, and so equals whatever
+    /// `null` equals.
+    EmptyIsNull,
+}
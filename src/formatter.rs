@@ -32,6 +32,10 @@ impl DiffFormatter {
                 DiffType::Removed => "-",
                 DiffType::Modified => "~",
                 DiffType::Moved => ">",
+                DiffType::Truncated => "!",
+                DiffType::Copied => "=",
+                DiffType::TypeChanged { .. } => "^",
+                DiffType::Coerced(_) => "*",
             };
 
             output.push_str(&format!("{} {}: ", prefix, diff.path));
@@ -48,10 +52,25 @@ impl DiffFormatter {
                             output.push_str(&format!("{:?}", value));
                         }
                     }
-                    DiffType::Modified => {
+                    DiffType::Modified | DiffType::Truncated => {
                         output.push_str(&format!("{:?} -> {:?}", diff.old_value, diff.new_value));
                     }
-                    DiffType::Moved => {
+                    DiffType::TypeChanged { old, new } => {
+                        output.push_str(&format!(
+                            "({} -> {}) {:?} -> {:?}",
+                            old, new, diff.old_value, diff.new_value
+                        ));
+                    }
+                    DiffType::Coerced(coercion) => {
+                        output.push_str(&format!(
+                            "({}) {:?} -> {:?}",
+                            coercion, diff.old_value, diff.new_value
+                        ));
+                    }
+                    DiffType::Moved | DiffType::Copied => {
+                        if let Some(from_path) = &diff.from_path {
+                            output.push_str(&format!("from {} ", from_path));
+                        }
                         if let Some(value) = &diff.new_value {
                             output.push_str(&format!("{:?}", value));
                         }
@@ -73,19 +92,36 @@ impl DiffFormatter {
                 "added": 0,
                 "removed": 0,
                 "modified": 0,
-                "moved": 0
+                "moved": 0,
+                "truncated": 0,
+                "copied": 0,
+                "type_changed": 0,
+                "coerced": 0
             }
         });
 
         let mut summary = result["summary"].clone();
 
         for diff in diffs {
-            let diff_json = serde_json::json!({
+            let mut diff_json = serde_json::json!({
                 "path": diff.path,
-                "type": format!("{:?}", diff.diff_type),
+                "type": type_name(&diff.diff_type),
                 "old_value": diff.old_value,
                 "new_value": diff.new_value
             });
+            if let Some(from_path) = &diff.from_path {
+                diff_json["from_path"] = from_path.clone().into();
+            }
+            match &diff.diff_type {
+                DiffType::TypeChanged { old, new } => {
+                    diff_json["old_type"] = old.to_string().into();
+                    diff_json["new_type"] = new.to_string().into();
+                }
+                DiffType::Coerced(coercion) => {
+                    diff_json["coercion"] = coercion.to_string().into();
+                }
+                _ => {}
+            }
 
             result["diffs"].as_array_mut().unwrap().push(diff_json);
 
@@ -102,6 +138,19 @@ impl DiffFormatter {
                 DiffType::Moved => {
                     summary["moved"] = (summary["moved"].as_i64().unwrap() + 1).into();
                 }
+                DiffType::Truncated => {
+                    summary["truncated"] = (summary["truncated"].as_i64().unwrap() + 1).into();
+                }
+                DiffType::Copied => {
+                    summary["copied"] = (summary["copied"].as_i64().unwrap() + 1).into();
+                }
+                DiffType::TypeChanged { .. } => {
+                    summary["type_changed"] =
+                        (summary["type_changed"].as_i64().unwrap() + 1).into();
+                }
+                DiffType::Coerced(_) => {
+                    summary["coerced"] = (summary["coerced"].as_i64().unwrap() + 1).into();
+                }
             }
         }
 
@@ -134,6 +183,10 @@ impl DiffFormatter {
                 DiffType::Removed => ("red", "-"),
                 DiffType::Modified => ("yellow", "~"),
                 DiffType::Moved => ("cyan", ">"),
+                DiffType::Truncated => ("magenta", "!"),
+                DiffType::Copied => ("blue", "="),
+                DiffType::TypeChanged { .. } => ("bright_yellow", "^"),
+                DiffType::Coerced(_) => ("white", "*"),
             };
 
             output.push_str(&format!("{} [{}]: ", symbol, color_name));
@@ -152,10 +205,25 @@ impl DiffFormatter {
                             output.push_str(&format!("{:?}", value));
                         }
                     }
-                    DiffType::Modified => {
+                    DiffType::Modified | DiffType::Truncated => {
                         output.push_str(&format!("{:?} -> {:?}", diff.old_value, diff.new_value));
                     }
-                    DiffType::Moved => {
+                    DiffType::TypeChanged { old, new } => {
+                        output.push_str(&format!(
+                            "({} -> {}) {:?} -> {:?}",
+                            old, new, diff.old_value, diff.new_value
+                        ));
+                    }
+                    DiffType::Coerced(coercion) => {
+                        output.push_str(&format!(
+                            "({}) {:?} -> {:?}",
+                            coercion, diff.old_value, diff.new_value
+                        ));
+                    }
+                    DiffType::Moved | DiffType::Copied => {
+                        if let Some(from_path) = &diff.from_path {
+                            output.push_str(&format!("from {} ", from_path));
+                        }
                         if let Some(value) = &diff.new_value {
                             output.push_str(&format!("{:?}", value));
                         }
@@ -170,6 +238,19 @@ impl DiffFormatter {
     }
 }
 
+fn type_name(diff_type: &DiffType) -> &'static str {
+    match diff_type {
+        DiffType::Added => "Added",
+        DiffType::Removed => "Removed",
+        DiffType::Modified => "Modified",
+        DiffType::Moved => "Moved",
+        DiffType::Truncated => "Truncated",
+        DiffType::Copied => "Copied",
+        DiffType::TypeChanged { .. } => "TypeChanged",
+        DiffType::Coerced(_) => "Coerced",
+    }
+}
+
 impl Default for DiffFormatter {
     fn default() -> Self {
         Self::new()
@@ -1,13 +1,47 @@
 use serde_json::Value;
 
+#[cfg(any(feature = "cbor", feature = "msgpack", feature = "toml"))]
+mod bytes;
+pub mod canonical;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+pub mod coercion;
+pub mod comparator;
+mod datetime;
 pub mod diff;
+pub mod equivalence;
 pub mod formatter;
+#[cfg(feature = "json5")]
+pub mod json5;
+pub mod limits;
+#[cfg(feature = "msgpack")]
+pub mod msgpack;
+pub mod ndjson;
+pub mod path;
+pub mod prepared;
+pub mod progress;
+mod stream;
+#[cfg(feature = "toml")]
+pub mod toml;
+pub mod tree_edit;
+#[cfg(feature = "yaml")]
+pub mod yaml;
 
 #[cfg(test)]
 mod tests;
 
-pub use diff::{Diff, DiffType, JsonDiff};
+pub use canonical::{canonicalize, fingerprint, Fingerprint};
+pub use coercion::Coercion;
+pub use comparator::{Comparison, ValueComparator};
+pub use diff::{Diff, DiffRef, DiffSink, DiffType, JsonDiff, JsonType};
+pub use equivalence::Equivalence;
 pub use formatter::DiffFormatter;
+pub use limits::{CancellationToken, DiffReport, Limit};
+pub use ndjson::{NdjsonDiff, RecordDiff};
+pub use path::PathPattern;
+pub use prepared::PreparedDocument;
+pub use progress::{Progress, ProgressObserver};
+pub use tree_edit::{edit_distance, EditCosts, EditScript};
 
 pub fn compare_json(json1: &str, json2: &str) -> Result<Vec<Diff>, String> {
     let v1: Value = serde_json::from_str(json1).map_err(|e| e.to_string())?;
@@ -17,7 +51,66 @@ pub fn compare_json(json1: &str, json2: &str) -> Result<Vec<Diff>, String> {
     Ok(differ.diff(&v1, &v2))
 }
 
+/// Like [`compare_json`], but accepts JSON5 input, which covers JSONC-style
+/// comments and trailing commas as well as unquoted keys and single quotes.
+#[cfg(feature = "json5")]
+pub fn compare_json5(json1: &str, json2: &str) -> Result<Vec<Diff>, String> {
+    let v1 = json5::from_json5_str(json1)?;
+    let v2 = json5::from_json5_str(json2)?;
+
+    let differ = JsonDiff::new();
+    Ok(differ.diff(&v1, &v2))
+}
+
 pub fn compare_values(v1: &Value, v2: &Value) -> Vec<Diff> {
     let differ = JsonDiff::new();
     differ.diff(v1, v2)
 }
+
+/// Scores how alike two values are, from 0.0 to 1.0. See
+/// [`JsonDiff::similarity`].
+pub fn similarity(v1: &Value, v2: &Value) -> f64 {
+    JsonDiff::new().similarity(v1, v2)
+}
+
+pub fn compare_ndjson(ndjson1: &str, ndjson2: &str) -> Result<Vec<RecordDiff>, String> {
+    NdjsonDiff::new()
+        .diff_readers(ndjson1.as_bytes(), ndjson2.as_bytes())
+        .collect()
+}
+
+#[cfg(feature = "yaml")]
+pub fn compare_yaml(yaml1: &str, yaml2: &str) -> Result<Vec<Diff>, String> {
+    let v1 = yaml::from_yaml_str(yaml1)?;
+    let v2 = yaml::from_yaml_str(yaml2)?;
+
+    let differ = JsonDiff::new();
+    Ok(differ.diff(&v1, &v2))
+}
+
+#[cfg(feature = "toml")]
+pub fn compare_toml(toml1: &str, toml2: &str) -> Result<Vec<Diff>, String> {
+    let v1 = toml::from_toml_str(toml1)?;
+    let v2 = toml::from_toml_str(toml2)?;
+
+    let differ = JsonDiff::new().comparator_for(JsonType::Object, toml::compare_datetimes);
+    Ok(differ.diff(&v1, &v2))
+}
+
+#[cfg(feature = "cbor")]
+pub fn compare_cbor(cbor1: &[u8], cbor2: &[u8]) -> Result<Vec<Diff>, String> {
+    let v1 = cbor::from_cbor_slice(cbor1)?;
+    let v2 = cbor::from_cbor_slice(cbor2)?;
+
+    let differ = JsonDiff::new();
+    Ok(differ.diff(&v1, &v2))
+}
+
+#[cfg(feature = "msgpack")]
+pub fn compare_msgpack(msgpack1: &[u8], msgpack2: &[u8]) -> Result<Vec<Diff>, String> {
+    let v1 = msgpack::from_msgpack_slice(msgpack1)?;
+    let v2 = msgpack::from_msgpack_slice(msgpack2)?;
+
+    let differ = JsonDiff::new();
+    Ok(differ.diff(&v1, &v2))
+}
@@ -0,0 +1,160 @@
+use crate::diff::Diff;
+use crate::progress::{Progress, ProgressObserver};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+// Reading the clock and the cancellation flag on every node would dominate
+// the cost of comparing small scalars, so both are polled periodically.
+const POLL_INTERVAL: usize = 256;
+
+/// A handle that stops a running diff from another thread.
+///
+/// Clones share the same flag, so keep one clone to call [`cancel`] on and
+/// hand the other to [`JsonDiff::cancellation`].
+///
+/// [`cancel`]: CancellationToken::cancel
+/// [`JsonDiff::cancellation`]: crate::JsonDiff::cancellation
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The limit that stopped a diff before it finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Diffs,
+    Nodes,
+    Deadline,
+    Cancelled,
+}
+
+/// The diffs found by [`JsonDiff::diff_report`], together with the limit
+/// that cut the traversal short, if any.
+///
+/// [`JsonDiff::diff_report`]: crate::JsonDiff::diff_report
+#[derive(Debug, Clone)]
+pub struct DiffReport {
+    pub diffs: Vec<Diff>,
+    pub stopped_by: Option<Limit>,
+}
+
+impl DiffReport {
+    /// Returns whether `diffs` is partial because a limit was hit.
+    pub fn is_truncated(&self) -> bool {
+        self.stopped_by.is_some()
+    }
+}
+
+/// The node, time and cancellation limits of one diff call, and the
+/// observer it reports progress to. Shared by reference with the worker
+/// threads of a parallel diff.
+pub(crate) struct Budget {
+    max_nodes: Option<usize>,
+    deadline: Option<Instant>,
+    cancellation: Option<CancellationToken>,
+    progress: Option<(Arc<dyn ProgressObserver>, Option<usize>)>,
+    nodes: AtomicUsize,
+    stopped: OnceLock<Limit>,
+}
+
+impl Budget {
+    pub(crate) fn new(
+        max_nodes: Option<usize>,
+        deadline: Option<Instant>,
+        cancellation: Option<CancellationToken>,
+    ) -> Self {
+        Self {
+            max_nodes,
+            deadline,
+            cancellation,
+            progress: None,
+            nodes: AtomicUsize::new(0),
+            stopped: OnceLock::new(),
+        }
+    }
+
+    pub(crate) fn unlimited() -> Self {
+        Self::new(None, None, None)
+    }
+
+    pub(crate) fn with_progress(
+        mut self,
+        observer: Arc<dyn ProgressObserver>,
+        estimated_total: Option<usize>,
+    ) -> Self {
+        self.progress = Some((observer, estimated_total));
+        self
+    }
+
+    /// Accounts for one more pair of values being compared.
+    pub(crate) fn visit(&self) -> ControlFlow<()> {
+        if self.is_stopped() {
+            return ControlFlow::Break(());
+        }
+
+        let visited = self.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.max_nodes.is_some_and(|max| visited > max) {
+            return self.stop(Limit::Nodes);
+        }
+        if visited % POLL_INTERVAL == 1 {
+            if self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return self.stop(Limit::Deadline);
+            }
+            if self
+                .cancellation
+                .as_ref()
+                .is_some_and(|token| token.is_cancelled())
+            {
+                return self.stop(Limit::Cancelled);
+            }
+            self.report(visited);
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Sends the final node count to the progress observer.
+    pub(crate) fn finish(&self) {
+        self.report(self.nodes.load(Ordering::Relaxed));
+    }
+
+    fn report(&self, nodes: usize) {
+        if let Some((observer, estimated_total)) = &self.progress {
+            observer.on_progress(Progress {
+                nodes,
+                estimated_total: estimated_total.map(|total| total.max(nodes)),
+            });
+        }
+    }
+
+    /// Records `limit` as the reason the traversal ended. The first limit
+    /// recorded wins.
+    pub(crate) fn stop(&self, limit: Limit) -> ControlFlow<()> {
+        let _ = self.stopped.set(limit);
+        ControlFlow::Break(())
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped.get().is_some()
+    }
+
+    pub(crate) fn stopped_by(&self) -> Option<Limit> {
+        self.stopped.get().copied()
+    }
+}
@@ -0,0 +1,86 @@
+use crate::bytes::{bytes_to_value, escape_key, tagged, to_hex};
+use rmpv::Value as MsgpackValue;
+use serde_json::{json, Map, Number, Value};
+
+/// Decodes a single MessagePack value into the JSON data model.
+///
+/// Binary values become `{"$bytes": "<hex>"}` and extension values become
+/// `{"$ext": {"type": <n>, "data": "<hex>"}}`. Map keys must be strings,
+/// and keys starting with `$` get another `$` prepended, so that a map such
+/// as `{"$bytes": "0102"}` stays distinct from a binary value.
+pub fn from_msgpack_slice(input: &[u8]) -> Result<Value, String> {
+    let mut reader = input;
+    let value = rmpv::decode::read_value(&mut reader).map_err(|e| e.to_string())?;
+    if !reader.is_empty() {
+        return Err(format!(
+            "{} trailing byte(s) after MessagePack value",
+            reader.len()
+        ));
+    }
+    msgpack_to_value(value, "")
+}
+
+fn msgpack_to_value(value: MsgpackValue, path: &str) -> Result<Value, String> {
+    match value {
+        MsgpackValue::Nil => Ok(Value::Null),
+        MsgpackValue::Boolean(b) => Ok(Value::Bool(b)),
+        MsgpackValue::Integer(i) => match (i.as_u64(), i.as_i64()) {
+            (Some(u), _) => Ok(Value::Number(u.into())),
+            (None, Some(n)) => Ok(Value::Number(n.into())),
+            (None, None) => Err(format!("integer {} at '{}' is out of range", i, path)),
+        },
+        MsgpackValue::F32(f) => float_to_value(f as f64, path),
+        MsgpackValue::F64(f) => float_to_value(f, path),
+        MsgpackValue::String(s) => s
+            .into_str()
+            .map(Value::String)
+            .ok_or_else(|| format!("invalid UTF-8 string at '{}'", path)),
+        MsgpackValue::Binary(bytes) => Ok(bytes_to_value(&bytes)),
+        MsgpackValue::Ext(kind, data) => {
+            Ok(tagged("$ext", json!({"type": kind, "data": to_hex(&data)})))
+        }
+        MsgpackValue::Array(items) => items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let item_path = if path.is_empty() {
+                    format!("[{}]", i)
+                } else {
+                    format!("{}[{}]", path, i)
+                };
+                msgpack_to_value(item, &item_path)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        MsgpackValue::Map(entries) => {
+            let mut map = Map::new();
+            for (key, item) in entries {
+                let key = match key {
+                    MsgpackValue::String(key) if key.is_str() => {
+                        escape_key(key.into_str().unwrap_or_default())
+                    }
+                    other => {
+                        return Err(format!("non-string map key {} at '{}'", other, path));
+                    }
+                };
+                let item_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                if map.contains_key(&key) {
+                    return Err(format!("duplicate map key at '{}'", item_path));
+                }
+                let item = msgpack_to_value(item, &item_path)?;
+                map.insert(key, item);
+            }
+            Ok(Value::Object(map))
+        }
+    }
+}
+
+fn float_to_value(f: f64, path: &str) -> Result<Value, String> {
+    Number::from_f64(f)
+        .map(Value::Number)
+        .ok_or_else(|| format!("float {} at '{}' cannot be represented in JSON", f, path))
+}
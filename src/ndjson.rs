@@ -0,0 +1,305 @@
+use crate::diff::{Diff, DiffType, JsonDiff};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::BufRead;
+
+/// The differences found for one pair of JSON Lines records.
+///
+/// A record present on only one side produces a single `Added` or `Removed`
+/// diff at the empty path carrying the whole record.
+#[derive(Debug, Clone)]
+pub struct RecordDiff {
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub key: Option<Value>,
+    pub diffs: Vec<Diff>,
+}
+
+pub struct NdjsonDiff {
+    differ: JsonDiff,
+    key_field: Option<String>,
+    strict_keys: bool,
+}
+
+impl NdjsonDiff {
+    pub fn new() -> Self {
+        Self {
+            differ: JsonDiff::new(),
+            key_field: None,
+            strict_keys: false,
+        }
+    }
+
+    pub fn differ(mut self, differ: JsonDiff) -> Self {
+        self.differ = differ;
+        self
+    }
+
+    /// Pairs records by the value of this top-level field instead of by
+    /// position. Keys must be unique within each input. A key that repeats
+    /// while its first record is still waiting for a partner is an error; see
+    /// [`NdjsonDiff::strict_keys`] to catch every repeat.
+    pub fn key_field(mut self, field: &str) -> Self {
+        self.key_field = Some(field.to_string());
+        self
+    }
+
+    /// Also makes a key that repeats after its first record was paired an
+    /// error. Without this, such a record is paired anew like any other.
+    /// Catching every repeat means keeping every key seen, so memory grows
+    /// with the number of records even when both inputs are in order.
+    pub fn strict_keys(mut self, strict: bool) -> Self {
+        self.strict_keys = strict;
+        self
+    }
+
+    /// Streams both inputs line by line, yielding one [`RecordDiff`] for
+    /// every pair of records that differ.
+    ///
+    /// Blank lines are skipped, so without a key field the nth record of one
+    /// input is paired with the nth record of the other, wherever the blank
+    /// lines fall. The reported line numbers are those of the input lines.
+    ///
+    /// When pairing by position only the current line of each input is in
+    /// memory. When pairing by key, records wait until their partner shows up,
+    /// so memory grows with how far the two inputs are out of order, plus the
+    /// keys seen so far with [`NdjsonDiff::strict_keys`].
+    pub fn diff_readers<R1: BufRead, R2: BufRead>(
+        &self,
+        old: R1,
+        new: R2,
+    ) -> RecordDiffs<'_, R1, R2> {
+        RecordDiffs {
+            ndjson: self,
+            old: Side::new(old),
+            new: Side::new(new),
+            pending_old: HashMap::new(),
+            pending_new: HashMap::new(),
+            seen_old: HashSet::new(),
+            seen_new: HashSet::new(),
+            ready: VecDeque::new(),
+            finished: false,
+        }
+    }
+}
+
+impl Default for NdjsonDiff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Side<R> {
+    reader: R,
+    line: usize,
+    done: bool,
+}
+
+impl<R: BufRead> Side<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: 0,
+            done: false,
+        }
+    }
+
+    fn next_record(&mut self) -> Result<Option<(usize, Value)>, String> {
+        let mut buf = String::new();
+        while !self.done {
+            buf.clear();
+            if self.reader.read_line(&mut buf).map_err(|e| e.to_string())? == 0 {
+                self.done = true;
+                break;
+            }
+            self.line += 1;
+            if buf.trim().is_empty() {
+                continue;
+            }
+            let value =
+                serde_json::from_str(&buf).map_err(|e| format!("line {}: {}", self.line, e))?;
+            return Ok(Some((self.line, value)));
+        }
+        Ok(None)
+    }
+}
+
+pub struct RecordDiffs<'a, R1, R2> {
+    ndjson: &'a NdjsonDiff,
+    old: Side<R1>,
+    new: Side<R2>,
+    pending_old: HashMap<String, (usize, Value, Value)>,
+    pending_new: HashMap<String, (usize, Value, Value)>,
+    seen_old: HashSet<String>,
+    seen_new: HashSet<String>,
+    ready: VecDeque<RecordDiff>,
+    finished: bool,
+}
+
+impl<R1: BufRead, R2: BufRead> RecordDiffs<'_, R1, R2> {
+    fn advance(&mut self) -> Result<(), String> {
+        let old = self.old.next_record()?;
+        let new = self.new.next_record()?;
+
+        if old.is_none() && new.is_none() {
+            self.finish();
+            return Ok(());
+        }
+
+        let ndjson = self.ndjson;
+        match &ndjson.key_field {
+            None => self.pair_by_position(old, new),
+            Some(field) => {
+                if let Some((line, record)) = old {
+                    self.pair_by_key(field, line, record, true)?;
+                }
+                if let Some((line, record)) = new {
+                    self.pair_by_key(field, line, record, false)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn pair_by_position(&mut self, old: Option<(usize, Value)>, new: Option<(usize, Value)>) {
+        let record = match (old, new) {
+            (Some((old_line, v1)), Some((new_line, v2))) => RecordDiff {
+                old_line: Some(old_line),
+                new_line: Some(new_line),
+                key: None,
+                diffs: self.ndjson.differ.diff(&v1, &v2),
+            },
+            (Some((line, v1)), None) => removed_record(line, None, v1),
+            (None, Some((line, v2))) => added_record(line, None, v2),
+            (None, None) => return,
+        };
+        if !record.diffs.is_empty() {
+            self.ready.push_back(record);
+        }
+    }
+
+    fn pair_by_key(
+        &mut self,
+        field: &str,
+        line: usize,
+        record: Value,
+        is_old: bool,
+    ) -> Result<(), String> {
+        let key = record
+            .get(field)
+            .cloned()
+            .ok_or_else(|| format!("line {}: record has no '{}' field", line, field))?;
+        let lookup = key.to_string();
+
+        let (seen, own, other) = if is_old {
+            (
+                &mut self.seen_old,
+                &mut self.pending_old,
+                &mut self.pending_new,
+            )
+        } else {
+            (
+                &mut self.seen_new,
+                &mut self.pending_new,
+                &mut self.pending_old,
+            )
+        };
+        let repeated = if self.ndjson.strict_keys {
+            !seen.insert(lookup.clone())
+        } else {
+            own.contains_key(&lookup)
+        };
+        if repeated {
+            return Err(format!("line {}: duplicate key {}", line, lookup));
+        }
+
+        match other.remove(&lookup) {
+            Some((other_line, _, other_record)) => {
+                let (old_line, new_line, v1, v2) = if is_old {
+                    (line, other_line, &record, &other_record)
+                } else {
+                    (other_line, line, &other_record, &record)
+                };
+                let diffs = self.ndjson.differ.diff(v1, v2);
+                if !diffs.is_empty() {
+                    self.ready.push_back(RecordDiff {
+                        old_line: Some(old_line),
+                        new_line: Some(new_line),
+                        key: Some(key),
+                        diffs,
+                    });
+                }
+            }
+            None => {
+                own.insert(lookup, (line, key, record));
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+
+        let mut removed: Vec<_> = self.pending_old.drain().map(|(_, entry)| entry).collect();
+        removed.sort_by_key(|(line, _, _)| *line);
+        for (line, key, record) in removed {
+            self.ready
+                .push_back(removed_record(line, Some(key), record));
+        }
+
+        let mut added: Vec<_> = self.pending_new.drain().map(|(_, entry)| entry).collect();
+        added.sort_by_key(|(line, _, _)| *line);
+        for (line, key, record) in added {
+            self.ready.push_back(added_record(line, Some(key), record));
+        }
+    }
+}
+
+impl<R1: BufRead, R2: BufRead> Iterator for RecordDiffs<'_, R1, R2> {
+    type Item = Result<RecordDiff, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.ready.pop_front() {
+                return Some(Ok(record));
+            }
+            if self.finished {
+                return None;
+            }
+            if let Err(e) = self.advance() {
+                self.finished = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+fn removed_record(line: usize, key: Option<Value>, record: Value) -> RecordDiff {
+    RecordDiff {
+        old_line: Some(line),
+        new_line: None,
+        key,
+        diffs: vec![Diff {
+            path: String::new(),
+            from_path: None,
+            diff_type: DiffType::Removed,
+            old_value: Some(record),
+            new_value: None,
+        }],
+    }
+}
+
+fn added_record(line: usize, key: Option<Value>, record: Value) -> RecordDiff {
+    RecordDiff {
+        old_line: None,
+        new_line: Some(line),
+        key,
+        diffs: vec![Diff {
+            path: String::new(),
+            from_path: None,
+            diff_type: DiffType::Added,
+            old_value: None,
+            new_value: Some(record),
+        }],
+    }
+}
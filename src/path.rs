@@ -0,0 +1,138 @@
+use std::fmt;
+
+/// A pattern over the diff paths `JsonDiff` reports, such as `a.b[0]`.
+///
+/// Patterns use the same syntax as paths, plus three wildcards: `*` matches
+/// any one object key, `[*]` matches any one array index and `**` matches
+/// any number of keys and indices, including none. So `users[*].id`
+/// matches `users[3].id`, and `**.updated_at` matches `updated_at` at any
+/// depth. The empty pattern matches only the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathPattern {
+    text: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    AnyKey,
+    AnyIndex,
+    AnyDepth,
+}
+
+// One step of a concrete path.
+enum Step<'p> {
+    Key(&'p str),
+    Index(usize),
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Self {
+        let segments = split(pattern)
+            .into_iter()
+            .map(|part| match part {
+                Part::Key("*") => Segment::AnyKey,
+                Part::Key("**") => Segment::AnyDepth,
+                Part::Key(key) => Segment::Key(key.to_string()),
+                Part::Bracket("*") => Segment::AnyIndex,
+                Part::Bracket(index) => match index.parse() {
+                    Ok(index) => Segment::Index(index),
+                    Err(_) => Segment::Key(format!("[{}]", index)),
+                },
+            })
+            .collect();
+        Self {
+            text: pattern.to_string(),
+            segments,
+        }
+    }
+
+    /// Returns whether `path`, in the form `JsonDiff` reports, matches.
+    pub fn matches(&self, path: &str) -> bool {
+        let steps = split(path).into_iter().map(|part| match part {
+            Part::Key(key) => Step::Key(key),
+            Part::Bracket(index) => index.parse().map_or(Step::Key(index), Step::Index),
+        });
+
+        // `reachable[i]` holds when the first `i` segments match the steps
+        // seen so far.
+        let n = self.segments.len();
+        let mut reachable = vec![false; n + 1];
+        reachable[0] = true;
+        self.skip_any_depth(&mut reachable);
+        for step in steps {
+            let mut next = vec![false; n + 1];
+            for (i, segment) in self.segments.iter().enumerate() {
+                if !reachable[i] {
+                    continue;
+                }
+                match (segment, &step) {
+                    (Segment::AnyDepth, _) => next[i] = true,
+                    (Segment::AnyKey, Step::Key(_)) | (Segment::AnyIndex, Step::Index(_)) => {
+                        next[i + 1] = true
+                    }
+                    (Segment::Key(k), Step::Key(key)) if k == key => next[i + 1] = true,
+                    (Segment::Index(i1), Step::Index(i2)) if i1 == i2 => next[i + 1] = true,
+                    _ => {}
+                }
+            }
+            reachable = next;
+            self.skip_any_depth(&mut reachable);
+        }
+        reachable[n]
+    }
+
+    // `**` may also match no steps at all.
+    fn skip_any_depth(&self, reachable: &mut [bool]) {
+        for (i, segment) in self.segments.iter().enumerate() {
+            if reachable[i] && *segment == Segment::AnyDepth {
+                reachable[i + 1] = true;
+            }
+        }
+    }
+}
+
+impl fmt::Display for PathPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl From<&str> for PathPattern {
+    fn from(pattern: &str) -> Self {
+        Self::new(pattern)
+    }
+}
+
+enum Part<'p> {
+    Key(&'p str),
+    Bracket(&'p str),
+}
+
+// Splits `a.b[0]` into `a`, `b` and `[0]`. A `[` without a closing `]` is
+// part of a key.
+fn split(path: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        if let Some(inner) = rest.strip_prefix('[') {
+            if let Some(end) = inner.find(']') {
+                parts.push(Part::Bracket(&inner[..end]));
+                rest = &inner[end + 1..];
+                rest = rest.strip_prefix('.').unwrap_or(rest);
+                continue;
+            }
+        }
+        let end = rest
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| c == '.' || c == '[')
+            .map_or(rest.len(), |(i, _)| i);
+        parts.push(Part::Key(&rest[..end]));
+        rest = &rest[end..];
+        rest = rest.strip_prefix('.').unwrap_or(rest);
+    }
+    parts
+}
@@ -0,0 +1,405 @@
+use crate::diff::number_key;
+use serde_json::{Map, Value};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::OnceLock;
+
+/// A document paired with a hash of every subtree, so that
+/// [`JsonDiff::diff_prepared`] can skip identical subtrees without looking
+/// inside them.
+///
+/// A container's hash is the wrapping sum of one hash per child, mixed with
+/// the child's index or key. Editing a value through [`set`] or [`remove`]
+/// therefore only rehashes the new value and the containers on the path to
+/// it, and removing an array element also rehashes that array.
+///
+/// Hashes are keyed randomly per process, so they cannot be precomputed to
+/// collide and are not meant to be stored.
+///
+/// [`JsonDiff::diff_prepared`]: crate::JsonDiff::diff_prepared
+/// [`set`]: PreparedDocument::set
+/// [`remove`]: PreparedDocument::remove
+pub struct PreparedDocument {
+    value: Value,
+    root: HashNode,
+}
+
+impl PreparedDocument {
+    pub fn new(value: Value) -> Self {
+        let root = HashNode::build(&value);
+        Self { value, root }
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn into_value(self) -> Value {
+        self.value
+    }
+
+    pub(crate) fn root(&self) -> &HashNode {
+        &self.root
+    }
+
+    /// Sets the value at the JSON Pointer `pointer`, returning the value it
+    /// replaced. Object members are inserted if missing, and `-` appends to
+    /// an array. The empty pointer replaces the whole document.
+    pub fn set(&mut self, pointer: &str, value: Value) -> Result<Option<Value>, String> {
+        self.edit(pointer, Some(value))
+    }
+
+    /// Removes and returns the value at the JSON Pointer `pointer`.
+    pub fn remove(&mut self, pointer: &str) -> Result<Value, String> {
+        self.edit(pointer, None)?
+            .ok_or_else(|| format!("no value at '{}'", pointer))
+    }
+
+    fn edit(&mut self, pointer: &str, value: Option<Value>) -> Result<Option<Value>, String> {
+        let tokens = parse_pointer(pointer)?;
+        let Some((last, parents)) = tokens.split_last() else {
+            let value = value.ok_or("cannot remove the document root")?;
+            self.root = HashNode::build(&value);
+            return Ok(Some(std::mem::replace(&mut self.value, value)));
+        };
+
+        // First pass: find the parent of the edited value, remembering every
+        // container on the way down.
+        let mut ancestors = Vec::with_capacity(tokens.len());
+        let mut current = (&self.value, &self.root);
+        for token in parents {
+            ancestors.push(current.1);
+            current = child(current.0, current.1, token)
+                .ok_or_else(|| format!("no value at '{}'", pointer))?;
+        }
+        let (parent_value, parent) = current;
+
+        let new_node = value.as_ref().map(HashNode::build);
+        let (slot, sum, len) = match (parent_value, &parent.children) {
+            (Value::Object(map), Children::Object(members)) => {
+                let old = members.get(last.as_str());
+                if old.is_none() && new_node.is_none() {
+                    return Err(format!("no value at '{}'", pointer));
+                }
+                let sum = parent
+                    .sum
+                    .wrapping_sub(old.map_or(0, |node| mix_key(last, node.hash)))
+                    .wrapping_add(new_node.as_ref().map_or(0, |node| mix_key(last, node.hash)));
+                let len = match (old, &new_node) {
+                    (None, Some(_)) => map.len() + 1,
+                    (Some(_), None) => map.len() - 1,
+                    _ => map.len(),
+                };
+                (Slot::Key(last.clone()), sum, len)
+            }
+            (Value::Array(items), Children::Array(nodes)) => {
+                match (array_index(last, items.len()), &new_node) {
+                    (Some(index), Some(node)) => {
+                        let sum = parent
+                            .sum
+                            .wrapping_sub(mix_index(index, nodes[index].hash))
+                            .wrapping_add(mix_index(index, node.hash));
+                        (Slot::Index(index), sum, items.len())
+                    }
+                    (Some(index), None) => {
+                        // Later elements shift down, so the whole array is
+                        // rehashed.
+                        let sum = nodes
+                            .iter()
+                            .enumerate()
+                            .filter(|&(i, _)| i != index)
+                            .enumerate()
+                            .fold(0u64, |sum, (i, (_, node))| {
+                                sum.wrapping_add(mix_index(i, node.hash))
+                            });
+                        (Slot::Index(index), sum, items.len() - 1)
+                    }
+                    (None, Some(node)) if last == "-" => {
+                        let sum = parent.sum.wrapping_add(mix_index(items.len(), node.hash));
+                        (Slot::Append, sum, items.len() + 1)
+                    }
+                    _ => return Err(format!("no value at '{}'", pointer)),
+                }
+            }
+            _ => return Err(format!("no value at '{}'", pointer)),
+        };
+
+        // Work out the new hash of every ancestor, bottom up, before touching
+        // anything.
+        let mut updates = vec![(sum, container_hash(&parent.children, len, sum))];
+        for (node, token) in ancestors.iter().zip(parents).rev() {
+            let old_child = child_node(node, token).map_or(0, |child| child.hash);
+            let new_child = updates.last().map_or(0, |&(_, hash)| hash);
+            let sum = match &node.children {
+                Children::Array(_) => {
+                    let index = token.parse().unwrap_or_default();
+                    node.sum
+                        .wrapping_sub(mix_index(index, old_child))
+                        .wrapping_add(mix_index(index, new_child))
+                }
+                _ => node
+                    .sum
+                    .wrapping_sub(mix_key(token, old_child))
+                    .wrapping_add(mix_key(token, new_child)),
+            };
+            let len = node.children.len();
+            updates.push((sum, container_hash(&node.children, len, sum)));
+        }
+
+        // Second pass: walk down again, applying the new hashes and finally
+        // the edit itself.
+        let mut value_ref = &mut self.value;
+        let mut node = &mut self.root;
+        for token in parents {
+            let (sum, hash) = updates.pop().unwrap_or_default();
+            node.sum = sum;
+            node.hash = hash;
+            value_ref = child_value_mut(value_ref, token)
+                .ok_or_else(|| format!("no value at '{}'", pointer))?;
+            node =
+                child_node_mut(node, token).ok_or_else(|| format!("no value at '{}'", pointer))?;
+        }
+        let (sum, hash) = updates.pop().unwrap_or_default();
+        node.sum = sum;
+        node.hash = hash;
+
+        let old = match (value_ref, &mut node.children, slot) {
+            (Value::Object(map), Children::Object(members), Slot::Key(key)) => match value {
+                Some(value) => {
+                    members.insert(key.clone(), new_node.unwrap_or_default());
+                    map.insert(key, value)
+                }
+                None => {
+                    members.remove(&key);
+                    map.remove(&key)
+                }
+            },
+            (Value::Array(items), Children::Array(nodes), Slot::Index(index)) => match value {
+                Some(value) => {
+                    nodes[index] = new_node.unwrap_or_default();
+                    Some(std::mem::replace(&mut items[index], value))
+                }
+                None => {
+                    nodes.remove(index);
+                    Some(items.remove(index))
+                }
+            },
+            (Value::Array(items), Children::Array(nodes), Slot::Append) => {
+                nodes.push(new_node.unwrap_or_default());
+                items.extend(value);
+                None
+            }
+            _ => None,
+        };
+        Ok(old)
+    }
+}
+
+impl From<Value> for PreparedDocument {
+    fn from(value: Value) -> Self {
+        Self::new(value)
+    }
+}
+
+enum Slot {
+    Key(String),
+    Index(usize),
+    Append,
+}
+
+#[derive(Default)]
+pub(crate) struct HashNode {
+    pub(crate) hash: u64,
+    sum: u64,
+    children: Children,
+}
+
+#[derive(Default)]
+enum Children {
+    #[default]
+    Leaf,
+    Array(Vec<HashNode>),
+    Object(HashMap<String, HashNode>),
+}
+
+impl Children {
+    fn len(&self) -> usize {
+        match self {
+            Children::Leaf => 0,
+            Children::Array(nodes) => nodes.len(),
+            Children::Object(members) => members.len(),
+        }
+    }
+}
+
+impl HashNode {
+    pub(crate) fn item(&self, index: usize) -> Option<&HashNode> {
+        match &self.children {
+            Children::Array(nodes) => nodes.get(index),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn member(&self, key: &str) -> Option<&HashNode> {
+        match &self.children {
+            Children::Object(members) => members.get(key),
+            _ => None,
+        }
+    }
+
+    // Builds the tree bottom up with an explicit stack, like the diff
+    // engine, so deeply nested documents cannot overflow the call stack.
+    pub(crate) fn build(value: &Value) -> HashNode {
+        enum Task<'a> {
+            Visit(&'a Value),
+            Array(usize),
+            Object(&'a Map<String, Value>),
+        }
+
+        let mut tasks = vec![Task::Visit(value)];
+        let mut built: Vec<HashNode> = Vec::new();
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Visit(Value::Array(items)) => {
+                    tasks.push(Task::Array(items.len()));
+                    tasks.extend(items.iter().rev().map(Task::Visit));
+                }
+                Task::Visit(Value::Object(map)) => {
+                    tasks.push(Task::Object(map));
+                    tasks.extend(map.values().rev().map(Task::Visit));
+                }
+                Task::Visit(scalar) => built.push(HashNode {
+                    hash: scalar_hash(scalar),
+                    sum: 0,
+                    children: Children::Leaf,
+                }),
+                Task::Array(len) => {
+                    let nodes = built.split_off(built.len() - len);
+                    let sum = nodes.iter().enumerate().fold(0u64, |sum, (i, node)| {
+                        sum.wrapping_add(mix_index(i, node.hash))
+                    });
+                    let children = Children::Array(nodes);
+                    built.push(HashNode {
+                        hash: container_hash(&children, len, sum),
+                        sum,
+                        children,
+                    });
+                }
+                Task::Object(map) => {
+                    let nodes = built.split_off(built.len() - map.len());
+                    let sum = map.keys().zip(&nodes).fold(0u64, |sum, (key, node)| {
+                        sum.wrapping_add(mix_key(key, node.hash))
+                    });
+                    let children = Children::Object(map.keys().cloned().zip(nodes).collect());
+                    built.push(HashNode {
+                        hash: container_hash(&children, map.len(), sum),
+                        sum,
+                        children,
+                    });
+                }
+            }
+        }
+        built.pop().unwrap_or_default()
+    }
+}
+
+fn hash_of<T: Hash>(value: T) -> u64 {
+    static STATE: OnceLock<RandomState> = OnceLock::new();
+    STATE.get_or_init(RandomState::new).hash_one(value)
+}
+
+fn scalar_hash(value: &Value) -> u64 {
+    match value {
+        Value::Null => hash_of(0u8),
+        Value::Bool(b) => hash_of((1u8, b)),
+        Value::Number(n) => hash_of((2u8, number_key(n))),
+        Value::String(s) => hash_of((3u8, s)),
+        Value::Array(_) | Value::Object(_) => 0,
+    }
+}
+
+fn container_hash(children: &Children, len: usize, sum: u64) -> u64 {
+    match children {
+        Children::Array(_) => hash_of((4u8, len, sum)),
+        _ => hash_of((5u8, len, sum)),
+    }
+}
+
+fn mix_index(index: usize, hash: u64) -> u64 {
+    hash_of((index, hash))
+}
+
+fn mix_key(key: &str, hash: u64) -> u64 {
+    hash_of((key, hash))
+}
+
+fn child<'v, 'n>(
+    value: &'v Value,
+    node: &'n HashNode,
+    token: &str,
+) -> Option<(&'v Value, &'n HashNode)> {
+    match value {
+        Value::Object(map) => Some((map.get(token)?, node.member(token)?)),
+        Value::Array(items) => {
+            let index = array_index(token, items.len())?;
+            Some((&items[index], node.item(index)?))
+        }
+        _ => None,
+    }
+}
+
+fn child_node<'n>(node: &'n HashNode, token: &str) -> Option<&'n HashNode> {
+    match &node.children {
+        Children::Array(nodes) => nodes.get(array_index(token, nodes.len())?),
+        _ => node.member(token),
+    }
+}
+
+fn child_node_mut<'n>(node: &'n mut HashNode, token: &str) -> Option<&'n mut HashNode> {
+    match &mut node.children {
+        Children::Array(nodes) => {
+            let index = array_index(token, nodes.len())?;
+            nodes.get_mut(index)
+        }
+        Children::Object(members) => members.get_mut(token),
+        Children::Leaf => None,
+    }
+}
+
+fn child_value_mut<'v>(value: &'v mut Value, token: &str) -> Option<&'v mut Value> {
+    match value {
+        Value::Object(map) => map.get_mut(token),
+        Value::Array(items) => {
+            let index = array_index(token, items.len())?;
+            items.get_mut(index)
+        }
+        _ => None,
+    }
+}
+
+/// Parses an array index token: decimal digits without leading zeros, less
+/// than `len`.
+fn array_index(token: &str, len: usize) -> Option<usize> {
+    if token.is_empty()
+        || !token.bytes().all(|b| b.is_ascii_digit())
+        || (token.len() > 1 && token.starts_with('0'))
+    {
+        return None;
+    }
+    token.parse().ok().filter(|&index| index < len)
+}
+
+/// Splits a JSON Pointer (RFC 6901) into unescaped reference tokens.
+fn parse_pointer(pointer: &str) -> Result<Vec<String>, String> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rest = pointer
+        .strip_prefix('/')
+        .ok_or_else(|| format!("invalid JSON pointer '{}'", pointer))?;
+    Ok(rest
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
@@ -0,0 +1,54 @@
+use serde_json::Value;
+
+/// A snapshot of how far a diff has got.
+///
+/// `nodes` counts the pairs of values compared so far. `estimated_total` is
+/// the size of the smaller input in nodes, which is how many pairs a diff of
+/// two similar documents compares. Unordered array matching can compare more
+/// pairs than that, so the estimate is raised to `nodes` when it falls behind.
+/// It is `None` when the size of the inputs is not known, as for streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub nodes: usize,
+    pub estimated_total: Option<usize>,
+}
+
+impl Progress {
+    /// Returns the completed fraction, between 0.0 and 1.0, or `None` if the
+    /// total is not known.
+    pub fn fraction(&self) -> Option<f64> {
+        match self.estimated_total? {
+            0 => Some(1.0),
+            total => Some(self.nodes as f64 / total as f64),
+        }
+    }
+}
+
+/// Receives periodic [`Progress`] updates from a running diff.
+///
+/// Parallel diffs report from the worker threads, so observers must be
+/// `Send + Sync`. Any `Fn(Progress) + Send + Sync` closure is an observer.
+pub trait ProgressObserver: Send + Sync {
+    fn on_progress(&self, progress: Progress);
+}
+
+impl<F: Fn(Progress) + Send + Sync> ProgressObserver for F {
+    fn on_progress(&self, progress: Progress) {
+        self(progress)
+    }
+}
+
+/// Counts every value in `value`, containers included.
+pub(crate) fn count_nodes(value: &Value) -> usize {
+    let mut count = 0;
+    let mut stack = vec![value];
+    while let Some(value) = stack.pop() {
+        count += 1;
+        match value {
+            Value::Array(items) => stack.extend(items),
+            Value::Object(map) => stack.extend(map.values()),
+            _ => {}
+        }
+    }
+    count
+}
@@ -0,0 +1,685 @@
+use crate::diff::{
+    index_path, key_path, push_index, push_key, Diff, DiffRef, DiffType, JsonDiff, JsonType,
+};
+use crate::limits::{Budget, Limit};
+use serde_json::{Map, Number, Value};
+use std::io::{BufReader, Bytes, Read};
+use std::ops::ControlFlow;
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    StartObject,
+    EndObject,
+    StartArray,
+    EndArray,
+    Key(String),
+    Scalar(Value),
+}
+
+// How deeply containers may nest in a streamed document. The readers and the
+// walker keep state for every open container, so without a bound a stream of
+// opening brackets would grow memory without limit.
+const MAX_NESTING: usize = 100_000;
+
+struct Container {
+    is_object: bool,
+    count: usize,
+    after_key: bool,
+}
+
+/// A pull parser that turns a byte stream into JSON events without building
+/// the document.
+struct EventReader<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    peeked: Option<u8>,
+    offset: usize,
+    stack: Vec<Container>,
+    root_done: bool,
+}
+
+impl<R: Read> EventReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            bytes: BufReader::new(reader).bytes(),
+            peeked: None,
+            offset: 0,
+            stack: Vec::new(),
+            root_done: false,
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, String> {
+        if self.peeked.is_none() {
+            self.peeked = match self.bytes.next() {
+                Some(byte) => Some(byte.map_err(|e| e.to_string())?),
+                None => None,
+            };
+        }
+        Ok(self.peeked)
+    }
+
+    fn bump(&mut self) -> Result<Option<u8>, String> {
+        let byte = self.peek()?;
+        self.peeked = None;
+        if byte.is_some() {
+            self.offset += 1;
+        }
+        Ok(byte)
+    }
+
+    fn peek_non_ws(&mut self) -> Result<Option<u8>, String> {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek()? {
+            self.bump()?;
+        }
+        self.peek()
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), String> {
+        match self.peek_non_ws()? {
+            Some(byte) if byte == expected => {
+                self.bump()?;
+                Ok(())
+            }
+            _ => Err(self.error(&format!("expected '{}'", expected as char))),
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{} at byte {}", message, self.offset)
+    }
+
+    fn next_event(&mut self) -> Result<Event, String> {
+        let top = self
+            .stack
+            .last()
+            .map(|container| (container.is_object, container.count, container.after_key));
+
+        match top {
+            None if self.root_done => Err(self.error("unexpected trailing content")),
+            None => self.start_value(),
+            Some((true, count, false)) => {
+                match self.peek_non_ws()? {
+                    Some(b'}') => {
+                        self.bump()?;
+                        self.end_container();
+                        return Ok(Event::EndObject);
+                    }
+                    _ if count > 0 => self.expect(b',')?,
+                    _ => {}
+                }
+                if self.peek_non_ws()? != Some(b'"') {
+                    return Err(self.error("expected object key"));
+                }
+                let key = self.read_string()?;
+                self.expect(b':')?;
+                if let Some(container) = self.stack.last_mut() {
+                    container.count += 1;
+                    container.after_key = true;
+                }
+                Ok(Event::Key(key))
+            }
+            Some((true, _, true)) => {
+                if let Some(container) = self.stack.last_mut() {
+                    container.after_key = false;
+                }
+                self.start_value()
+            }
+            Some((false, count, _)) => {
+                match self.peek_non_ws()? {
+                    Some(b']') => {
+                        self.bump()?;
+                        self.end_container();
+                        return Ok(Event::EndArray);
+                    }
+                    _ if count > 0 => self.expect(b',')?,
+                    _ => {}
+                }
+                if let Some(container) = self.stack.last_mut() {
+                    container.count += 1;
+                }
+                self.start_value()
+            }
+        }
+    }
+
+    fn end_container(&mut self) {
+        self.stack.pop();
+        if self.stack.is_empty() {
+            self.root_done = true;
+        }
+    }
+
+    fn start_value(&mut self) -> Result<Event, String> {
+        if let Some(b'{' | b'[') = self.peek_non_ws()? {
+            if self.stack.len() >= MAX_NESTING {
+                return Err(self.error("nesting too deep"));
+            }
+        }
+        let event = match self.peek_non_ws()? {
+            Some(b'{') => {
+                self.bump()?;
+                self.stack.push(Container {
+                    is_object: true,
+                    count: 0,
+                    after_key: false,
+                });
+                return Ok(Event::StartObject);
+            }
+            Some(b'[') => {
+                self.bump()?;
+                self.stack.push(Container {
+                    is_object: false,
+                    count: 0,
+                    after_key: false,
+                });
+                return Ok(Event::StartArray);
+            }
+            Some(b'"') => Event::Scalar(Value::String(self.read_string()?)),
+            Some(b't') => self.read_literal("true", Value::Bool(true))?,
+            Some(b'f') => self.read_literal("false", Value::Bool(false))?,
+            Some(b'n') => self.read_literal("null", Value::Null)?,
+            Some(b'-' | b'0'..=b'9') => Event::Scalar(Value::Number(self.read_number()?)),
+            Some(_) => return Err(self.error("expected value")),
+            None => return Err(self.error("unexpected end of input")),
+        };
+        if self.stack.is_empty() {
+            self.root_done = true;
+        }
+        Ok(event)
+    }
+
+    fn read_string(&mut self) -> Result<String, String> {
+        self.bump()?;
+        let mut raw = vec![b'"'];
+        loop {
+            match self.bump()? {
+                Some(b'"') => {
+                    raw.push(b'"');
+                    break;
+                }
+                Some(b'\\') => {
+                    raw.push(b'\\');
+                    match self.bump()? {
+                        Some(byte) => raw.push(byte),
+                        None => return Err(self.error("unterminated string")),
+                    }
+                }
+                Some(byte) => raw.push(byte),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+        serde_json::from_slice(&raw).map_err(|e| self.error(&e.to_string()))
+    }
+
+    fn read_number(&mut self) -> Result<Number, String> {
+        let mut raw = Vec::new();
+        while let Some(byte @ (b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) = self.peek()? {
+            raw.push(byte);
+            self.bump()?;
+        }
+        serde_json::from_slice(&raw).map_err(|_| self.error("invalid number"))
+    }
+
+    fn read_literal(&mut self, literal: &str, value: Value) -> Result<Event, String> {
+        for expected in literal.bytes() {
+            if self.bump()? != Some(expected) {
+                return Err(self.error(&format!("expected '{}'", literal)));
+            }
+        }
+        Ok(Event::Scalar(value))
+    }
+
+    /// Buffers the value that begins with `first` into memory.
+    fn read_value(&mut self, first: Event) -> Result<Value, String> {
+        let mut partial: Vec<(Value, Option<String>)> = Vec::new();
+        let mut event = first;
+        loop {
+            let completed = match event {
+                Event::StartObject => {
+                    partial.push((Value::Object(Map::new()), None));
+                    None
+                }
+                Event::StartArray => {
+                    partial.push((Value::Array(Vec::new()), None));
+                    None
+                }
+                Event::Key(key) => {
+                    if let Some((_, pending_key)) = partial.last_mut() {
+                        *pending_key = Some(key);
+                    }
+                    None
+                }
+                Event::EndObject | Event::EndArray => partial.pop().map(|(value, _)| value),
+                Event::Scalar(value) => Some(value),
+            };
+
+            if let Some(value) = completed {
+                match partial.last_mut() {
+                    None => return Ok(value),
+                    Some((Value::Array(items), _)) => items.push(value),
+                    Some((Value::Object(map), key)) => {
+                        map.insert(key.take().unwrap_or_default(), value);
+                    }
+                    Some(_) => unreachable!(),
+                }
+            }
+            event = self.next_event()?;
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        match self.peek_non_ws()? {
+            None => Ok(()),
+            Some(_) => Err(self.error("unexpected trailing content")),
+        }
+    }
+}
+
+// A container that both documents are inside of, walked in lockstep.
+// Frames hold the length of their path in the walker's path buffer.
+enum Frame {
+    Array {
+        path_len: usize,
+        depth: usize,
+        index: usize,
+    },
+    Object(ObjectFrame),
+}
+
+struct ObjectFrame {
+    path_len: usize,
+    depth: usize,
+    pending_left: Map<String, Value>,
+    pending_right: Map<String, Value>,
+    left_open: bool,
+    right_open: bool,
+}
+
+struct StreamWalker<'a, R1: Read, R2: Read, F: FnMut(Diff)> {
+    differ: &'a JsonDiff,
+    budget: &'a Budget,
+    diffs_left: Option<usize>,
+    // The path of the innermost frame, a prefix of which is the path of
+    // every other frame on the stack.
+    path: String,
+    left: EventReader<R1>,
+    right: EventReader<R2>,
+    on_diff: F,
+}
+
+impl<R1: Read, R2: Read, F: FnMut(Diff)> StreamWalker<'_, R1, R2, F> {
+    // Walks the documents with an explicit stack of open containers, so
+    // deeply nested input cannot overflow the call stack. The walk ends
+    // early once a limit stops the budget.
+    fn walk(&mut self, e1: Event, e2: Event) -> Result<(), String> {
+        let mut stack = Vec::new();
+        self.enter(e1, e2, 0, 0, &mut stack)?;
+        while let Some(frame) = stack.pop() {
+            if self.budget.is_stopped() {
+                break;
+            }
+            match frame {
+                Frame::Array {
+                    path_len,
+                    depth,
+                    index,
+                } => self.step_arrays(path_len, depth, index, &mut stack)?,
+                Frame::Object(object) => self.step_objects(object, &mut stack)?,
+            }
+        }
+        Ok(())
+    }
+
+    // Compares the values starting with `e1` and `e2`, pushing a frame if
+    // both are containers that can be walked in lockstep. Containers at
+    // `max_depth` are buffered, so the diff truncates them as usual.
+    fn enter(
+        &mut self,
+        e1: Event,
+        e2: Event,
+        path_len: usize,
+        depth: usize,
+        stack: &mut Vec<Frame>,
+    ) -> Result<(), String> {
+        let at_max_depth = self.differ.max_depth.is_some_and(|max| depth >= max);
+        let path = &self.path[..path_len];
+        match (e1, e2) {
+            (Event::StartArray, Event::StartArray)
+                if !at_max_depth
+                    && !self.differ.ignore_order
+                    && !self
+                        .differ
+                        .has_comparator(path, JsonType::Array, JsonType::Array) =>
+            {
+                if self.budget.visit().is_break() {
+                    return Ok(());
+                }
+                stack.push(Frame::Array {
+                    path_len,
+                    depth,
+                    index: 0,
+                });
+            }
+            (Event::StartObject, Event::StartObject)
+                if !at_max_depth
+                    && self.differ.rename_threshold.is_none()
+                    && !self
+                        .differ
+                        .has_comparator(path, JsonType::Object, JsonType::Object) =>
+            {
+                if self.budget.visit().is_break() {
+                    return Ok(());
+                }
+                stack.push(Frame::Object(ObjectFrame {
+                    path_len,
+                    depth,
+                    pending_left: Map::new(),
+                    pending_right: Map::new(),
+                    left_open: true,
+                    right_open: true,
+                }));
+            }
+            (e1, e2) => {
+                let v1 = self.left.read_value(e1)?;
+                let v2 = self.right.read_value(e2)?;
+                let _ = self.emit_buffered(&v1, &v2, path_len, depth);
+            }
+        }
+        Ok(())
+    }
+
+    fn step_arrays(
+        &mut self,
+        path_len: usize,
+        depth: usize,
+        index: usize,
+        stack: &mut Vec<Frame>,
+    ) -> Result<(), String> {
+        let e1 = self.left.next_event()?;
+        let e2 = self.right.next_event()?;
+        match (e1, e2) {
+            (Event::EndArray, Event::EndArray) => Ok(()),
+            (Event::EndArray, e2) => {
+                let value = self.right.read_value(e2)?;
+                let new_path = index_path(&self.path[..path_len], index);
+                if self.emit_added(new_path, value).is_break() {
+                    return Ok(());
+                }
+                self.drain_array(path_len, index + 1, false)
+            }
+            (e1, Event::EndArray) => {
+                let value = self.left.read_value(e1)?;
+                let new_path = index_path(&self.path[..path_len], index);
+                if self.emit_removed(new_path, value).is_break() {
+                    return Ok(());
+                }
+                self.drain_array(path_len, index + 1, true)
+            }
+            (e1, e2) => {
+                stack.push(Frame::Array {
+                    path_len,
+                    depth,
+                    index: index + 1,
+                });
+                let new_len = self.enter_index(path_len, index);
+                self.enter(e1, e2, new_len, depth + 1, stack)
+            }
+        }
+    }
+
+    fn drain_array(&mut self, path_len: usize, mut index: usize, left: bool) -> Result<(), String> {
+        loop {
+            let event = if left {
+                self.left.next_event()?
+            } else {
+                self.right.next_event()?
+            };
+            if event == Event::EndArray {
+                return Ok(());
+            }
+
+            let new_path = index_path(&self.path[..path_len], index);
+            let flow = if left {
+                let value = self.left.read_value(event)?;
+                self.emit_removed(new_path, value)
+            } else {
+                let value = self.right.read_value(event)?;
+                self.emit_added(new_path, value)
+            };
+            if flow.is_break() {
+                return Ok(());
+            }
+            index += 1;
+        }
+    }
+
+    // Members that appear in the same order on both sides are compared as
+    // they stream past. A member whose key differs from the other side's is
+    // buffered until its partner turns up or the object ends.
+    fn step_objects(
+        &mut self,
+        mut object: ObjectFrame,
+        stack: &mut Vec<Frame>,
+    ) -> Result<(), String> {
+        let k1 = if object.left_open {
+            self.next_key(true)?
+        } else {
+            None
+        };
+        let k2 = if object.right_open {
+            self.next_key(false)?
+        } else {
+            None
+        };
+        object.left_open = k1.is_some();
+        object.right_open = k2.is_some();
+
+        let path_len = object.path_len;
+        let depth = object.depth;
+        if let (Some(k1), Some(k2)) = (&k1, &k2) {
+            if k1 == k2 {
+                let e1 = self.left.next_event()?;
+                let e2 = self.right.next_event()?;
+                stack.push(Frame::Object(object));
+                let new_len = self.enter_key(path_len, k1);
+                return self.enter(e1, e2, new_len, depth + 1, stack);
+            }
+        }
+
+        if let Some(key) = k1 {
+            let event = self.left.next_event()?;
+            let value = self.left.read_value(event)?;
+            match object.pending_right.remove(&key) {
+                Some(other) => {
+                    let new_len = self.enter_key(path_len, &key);
+                    if self
+                        .emit_buffered(&value, &other, new_len, depth + 1)
+                        .is_break()
+                    {
+                        return Ok(());
+                    }
+                }
+                None => {
+                    object.pending_left.insert(key, value);
+                }
+            }
+        }
+        if let Some(key) = k2 {
+            let event = self.right.next_event()?;
+            let value = self.right.read_value(event)?;
+            match object.pending_left.remove(&key) {
+                Some(other) => {
+                    let new_len = self.enter_key(path_len, &key);
+                    if self
+                        .emit_buffered(&other, &value, new_len, depth + 1)
+                        .is_break()
+                    {
+                        return Ok(());
+                    }
+                }
+                None => {
+                    object.pending_right.insert(key, value);
+                }
+            }
+        }
+
+        if object.left_open || object.right_open {
+            stack.push(Frame::Object(object));
+            return Ok(());
+        }
+
+        for (key, value) in object.pending_left {
+            let new_path = key_path(&self.path[..path_len], &key);
+            if !self.differ.member_implied(Some(&value), None, &new_path)
+                && self.emit_removed(new_path, value).is_break()
+            {
+                return Ok(());
+            }
+        }
+        for (key, value) in object.pending_right {
+            let new_path = key_path(&self.path[..path_len], &key);
+            if !self.differ.member_implied(None, Some(&value), &new_path)
+                && self.emit_added(new_path, value).is_break()
+            {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    fn next_key(&mut self, left: bool) -> Result<Option<String>, String> {
+        let event = if left {
+            self.left.next_event()?
+        } else {
+            self.right.next_event()?
+        };
+        match event {
+            Event::Key(key) => Ok(Some(key)),
+            Event::EndObject => Ok(None),
+            _ => Err("expected object key".to_string()),
+        }
+    }
+
+    // Replaces whatever follows the path of length `path_len` in the buffer
+    // with the path of its item at `index`, returning the new length.
+    fn enter_index(&mut self, path_len: usize, index: usize) -> usize {
+        self.path.truncate(path_len);
+        push_index(&mut self.path, index);
+        self.path.len()
+    }
+
+    // Like `enter_index`, for the member at `key`.
+    fn enter_key(&mut self, path_len: usize, key: &str) -> usize {
+        self.path.truncate(path_len);
+        push_key(&mut self.path, key);
+        self.path.len()
+    }
+
+    fn emit_buffered(
+        &mut self,
+        v1: &Value,
+        v2: &Value,
+        path_len: usize,
+        depth: usize,
+    ) -> ControlFlow<()> {
+        let (differ, budget) = (self.differ, self.budget);
+        // The buffer is moved out while `emit` borrows the walker.
+        let path = std::mem::take(&mut self.path);
+        let mut emit = |diff: DiffRef| self.emit(diff.into_owned());
+        let flow = differ.diff_values(v1, v2, &path[..path_len], depth, &mut emit, budget);
+        self.path = path;
+        flow
+    }
+
+    // Delivers `diff`, and stops the walk once `max_diffs` diffs have been
+    // delivered.
+    fn emit(&mut self, diff: Diff) -> ControlFlow<()> {
+        if self.diffs_left == Some(0) {
+            return self.budget.stop(Limit::Diffs);
+        }
+        (self.on_diff)(diff);
+        if let Some(left) = &mut self.diffs_left {
+            *left -= 1;
+            if *left == 0 {
+                return self.budget.stop(Limit::Diffs);
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn emit_added(&mut self, path: String, value: Value) -> ControlFlow<()> {
+        self.emit(Diff {
+            path,
+            from_path: None,
+            diff_type: DiffType::Added,
+            old_value: None,
+            new_value: Some(value),
+        })
+    }
+
+    fn emit_removed(&mut self, path: String, value: Value) -> ControlFlow<()> {
+        self.emit(Diff {
+            path,
+            from_path: None,
+            diff_type: DiffType::Removed,
+            old_value: Some(value),
+            new_value: None,
+        })
+    }
+}
+
+impl JsonDiff {
+    /// Diffs two JSON documents read from byte streams, calling `on_diff` for
+    /// each difference as soon as it is found.
+    ///
+    /// Both documents are walked in lockstep, so memory stays bounded when
+    /// they share structure and member order. Only subtrees that cannot be
+    /// compared in passing are buffered: added or removed values, values
+    /// whose type changed, object members that appear in a different order,
+    /// arrays when `ignore_order` is set, objects when renames are detected,
+    /// containers that a registered comparator applies to and containers at
+    /// `max_depth`.
+    ///
+    /// The diff count, node, time and cancellation limits apply as they do
+    /// to [`JsonDiff::diff_report`], and the limit that cut the walk short,
+    /// if any, is returned. The rest of the input is then left unread, so
+    /// syntax errors after that point are not reported. The size of a
+    /// stream is not known up front, so progress reports carry no estimated
+    /// total.
+    ///
+    /// Documents with containers nested more than 100,000 deep are rejected
+    /// with an error, since the walk keeps state for every open container.
+    pub fn diff_readers<R1, R2, F>(
+        &self,
+        r1: R1,
+        r2: R2,
+        on_diff: F,
+    ) -> Result<Option<Limit>, String>
+    where
+        R1: Read,
+        R2: Read,
+        F: FnMut(Diff),
+    {
+        let budget = self.budget_with_estimate(|| None);
+        let mut walker = StreamWalker {
+            differ: self,
+            budget: &budget,
+            diffs_left: self.max_diffs,
+            path: String::new(),
+            left: EventReader::new(r1),
+            right: EventReader::new(r2),
+            on_diff,
+        };
+
+        let e1 = walker.left.next_event()?;
+        let e2 = walker.right.next_event()?;
+        walker.walk(e1, e2)?;
+        budget.finish();
+        if let Some(limit) = budget.stopped_by() {
+            return Ok(Some(limit));
+        }
+        walker.left.finish()?;
+        walker.right.finish()?;
+        Ok(None)
+    }
+}
@@ -1,7 +1,15 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-    use crate::{compare_json, compare_values, DiffFormatter, JsonDiff};
-    use serde_json::json;
+    use crate::{
+        canonicalize, compare_json, compare_ndjson, compare_values, edit_distance, fingerprint,
+        similarity, CancellationToken, Coercion, Comparison, Diff, DiffFormatter, DiffRef,
+        DiffSink, DiffType, EditCosts, Equivalence, JsonDiff, JsonType, Limit, NdjsonDiff,
+        PathPattern, PreparedDocument, Progress, ValueComparator,
+    };
+    use serde_json::{json, Value};
+    use std::ops::ControlFlow;
+    use std::time::Instant;
 
     #[test]
     fn test_identical_objects() {
@@ -216,4 +224,1469 @@ mod tests {
         let result = compare_json(json1, json2);
         assert!(result.is_err());
     }
+
+    #[cfg(feature = "arbitrary_precision")]
+    #[test]
+    fn test_arbitrary_precision_numbers() {
+        let json1 = r#"{"amount": 12345678901234567890.123456789, "id": 1.50}"#;
+        let json2 = r#"{"amount": 12345678901234567890.123456788, "id": 15e-1}"#;
+
+        let diffs = compare_json(json1, json2).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].path, "amount");
+
+        let output = DiffFormatter::new().format(&diffs);
+        assert!(output.contains("12345678901234567890.123456789"));
+        assert!(output.contains("12345678901234567890.123456788"));
+
+        let output = DiffFormatter::new().format_json(&diffs);
+        assert!(output.contains("12345678901234567890.123456789"));
+    }
+
+    #[cfg(feature = "arbitrary_precision")]
+    #[test]
+    fn test_arbitrary_precision_zero_and_exponent() {
+        let diffs = compare_json("[0, -0.0, 100, 1.0]", "[0e10, 0, 1e2, 1]").unwrap();
+        assert!(diffs.is_empty());
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_compare_yaml() {
+        let yaml1 = "name: web\nreplicas: 2\nports:\n  - 80\n  - 443\nenabled: true\n";
+        let yaml2 = "name: web\nreplicas: 3\nports: [80, 443]\nenabled: 'true'\n";
+
+        let diffs = crate::compare_yaml(yaml1, yaml2).unwrap();
+        let mut paths: Vec<&str> = diffs.iter().map(|d| d.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["enabled", "replicas"]);
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_yaml_rejects_non_json_constructs() {
+        use crate::yaml::from_yaml_str;
+
+        assert_eq!(
+            from_yaml_str("a: 1.5\nb: ~\nc: [x, 2]").unwrap(),
+            json!({"a": 1.5, "b": null, "c": ["x", 2]})
+        );
+        assert!(from_yaml_str("base: &b {x: 1}\nother: *b").is_err());
+        assert!(from_yaml_str("1: one").is_err());
+        assert!(from_yaml_str("[a]: one").is_err());
+        assert!(from_yaml_str("a: 1\na: 2").is_err());
+        assert!(from_yaml_str("a: .inf").is_err());
+        assert!(from_yaml_str("a: !custom 1").is_err());
+        assert!(from_yaml_str("--- 1\n--- 2").is_err());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_compare_toml() {
+        let toml1 = "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n\n[[bin]]\nname = \"a\"\n";
+        let toml2 = "[package]\nname = \"demo\"\nversion = \"0.2.0\"\n\n[[bin]]\nname = \"b\"\n";
+
+        let diffs = crate::compare_toml(toml1, toml2).unwrap();
+        let mut paths: Vec<&str> = diffs.iter().map(|d| d.path.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["bin[0].name", "package.version"]);
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_datetimes() {
+        use crate::toml::from_toml_str;
+
+        let value = from_toml_str(
+            "a = 1979-05-27T00:32:00.500-07:00\nb = 1979-05-27T07:32:00\nc = 1979-05-27\nd = 07:32:00",
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "a": {"$offset_datetime": "1979-05-27T07:32:00.5Z"},
+                "b": {"$local_datetime": "1979-05-27T07:32:00"},
+                "c": {"$local_date": "1979-05-27"},
+                "d": {"$local_time": "07:32:00"}
+            })
+        );
+
+        let same_instant =
+            crate::compare_toml("t = 2024-01-01T00:30:00+01:00", "t = 2023-12-31T23:30:00Z");
+        assert!(same_instant.unwrap().is_empty());
+
+        let string_vs_date = crate::compare_toml("t = \"1979-05-27\"", "t = 1979-05-27").unwrap();
+        assert_eq!(string_vs_date.len(), 1);
+
+        let changed = crate::compare_toml("t = 1979-05-27", "t = 1979-05-28").unwrap();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].path, "t");
+        assert_eq!(changed[0].diff_type, DiffType::Modified);
+
+        // A table spelled like a datetime is escaped, so it stays distinct.
+        let lookalike = "t = { \"$local_date\" = \"1979-05-27\" }";
+        assert_eq!(
+            from_toml_str(lookalike).unwrap(),
+            json!({"t": {"$$local_date": "1979-05-27"}})
+        );
+        let table_vs_date = crate::compare_toml("t = 1979-05-27", lookalike).unwrap();
+        assert_eq!(table_vs_date.len(), 1);
+        assert_eq!(table_vs_date[0].path, "t");
+
+        assert!(from_toml_str("x = nan").is_err());
+    }
+
+    #[cfg(feature = "json5")]
+    #[test]
+    fn test_compare_json5() {
+        let json1 = r#"{
+            // editor settings
+            "tabSize": 4,
+            "rulers": [80, 120,],
+        }"#;
+        let json2 = r#"{
+            /* block comment */
+            tabSize: 2,
+            'rulers': [80, 120],
+        }"#;
+
+        let diffs = crate::compare_json5(json1, json2).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].path, "tabSize");
+        assert_eq!(diffs[0].old_value, Some(json!(4)));
+
+        assert!(crate::compare_json5("{", "{}").is_err());
+        assert!(crate::compare_json5("{a: NaN}", "{a: null}").is_err());
+        assert!(crate::compare_json5("[1]", "[-Infinity]").is_err());
+        assert_eq!(
+            crate::json5::from_json5_str("{a: [0x10, .5, 'x', null, true]}").unwrap(),
+            json!({"a": [16, 0.5, "x", null, true]})
+        );
+    }
+
+    #[test]
+    fn test_ndjson_by_line() {
+        let old = "{\"id\": 1, \"v\": \"a\"}\n{\"id\": 2, \"v\": \"b\"}\n";
+        let new = "{\"id\": 1, \"v\": \"a\"}\n\n{\"id\": 2, \"v\": \"c\"}\n{\"id\": 3}\n";
+
+        // Blank lines are skipped, so the second records are paired even
+        // though they sit on different lines.
+        let records = compare_ndjson(old, new).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].old_line, Some(2));
+        assert_eq!(records[0].new_line, Some(3));
+        assert_eq!(records[0].diffs[0].path, "v");
+        assert_eq!(records[1].old_line, None);
+        assert_eq!(records[1].new_line, Some(4));
+        assert_eq!(format!("{:?}", records[1].diffs[0].diff_type), "Added");
+        assert_eq!(records[1].diffs[0].new_value, Some(json!({"id": 3})));
+    }
+
+    #[test]
+    fn test_ndjson_by_key() {
+        let old = "{\"id\": 1, \"v\": 1}\n{\"id\": 2, \"v\": 2}\n{\"id\": 3, \"v\": 3}\n";
+        let new = "{\"id\": 3, \"v\": 3}\n{\"id\": 1, \"v\": 10}\n{\"id\": 4, \"v\": 4}\n";
+
+        let records: Vec<_> = NdjsonDiff::new()
+            .key_field("id")
+            .diff_readers(old.as_bytes(), new.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].key, Some(json!(1)));
+        assert_eq!(records[0].diffs[0].path, "v");
+        assert_eq!(records[1].key, Some(json!(2)));
+        assert_eq!(format!("{:?}", records[1].diffs[0].diff_type), "Removed");
+        assert_eq!(records[2].key, Some(json!(4)));
+        assert_eq!(format!("{:?}", records[2].diffs[0].diff_type), "Added");
+
+        let missing_key = NdjsonDiff::new()
+            .key_field("id")
+            .diff_readers("{}".as_bytes(), "".as_bytes())
+            .next()
+            .unwrap();
+        assert!(missing_key.is_err());
+
+        // A key that repeats while its first record is pending is an error.
+        // One that repeats after pairing is only caught with `strict_keys`.
+        let new = "{\"id\": 2}\n{\"id\": 1}\n";
+        let diff = |old: &str, strict: bool| {
+            NdjsonDiff::new()
+                .key_field("id")
+                .strict_keys(strict)
+                .diff_readers(old.as_bytes(), new.as_bytes())
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let pending = "{\"id\": 1}\n{\"id\": 1}\n";
+        let paired = "{\"id\": 1}\n{\"id\": 2}\n{\"id\": 1}\n";
+        assert!(diff(pending, false)
+            .unwrap_err()
+            .contains("duplicate key 1"));
+        assert!(diff(paired, true).unwrap_err().contains("duplicate key 1"));
+
+        let records = diff(paired, false).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].old_line, Some(3));
+        assert_eq!(records[0].diffs[0].diff_type, DiffType::Removed);
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn test_compare_cbor() {
+        use ciborium::value::Value as Cbor;
+
+        let encode = |value: Cbor| {
+            let mut out = Vec::new();
+            ciborium::into_writer(&value, &mut out).unwrap();
+            out
+        };
+        let doc = |payload: &[u8]| {
+            Cbor::Map(vec![
+                (Cbor::Text("id".into()), Cbor::Integer(7.into())),
+                (Cbor::Text("payload".into()), Cbor::Bytes(payload.to_vec())),
+            ])
+        };
+
+        let diffs = crate::compare_cbor(&encode(doc(&[1, 2])), &encode(doc(&[1, 3]))).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].path, "payload.$bytes");
+        assert_eq!(diffs[0].old_value, Some(json!("0102")));
+
+        let text_key = encode(Cbor::Map(vec![(Cbor::Integer(1.into()), Cbor::Null)]));
+        assert!(crate::cbor::from_cbor_slice(&text_key).is_err());
+
+        // A map that looks like a wrapped byte string stays distinct from one.
+        let lookalike = Cbor::Map(vec![(
+            Cbor::Text("$bytes".into()),
+            Cbor::Text("0102".into()),
+        )]);
+        let diffs =
+            crate::compare_cbor(&encode(lookalike.clone()), &encode(Cbor::Bytes(vec![1, 2])))
+                .unwrap();
+        assert!(!diffs.is_empty());
+        assert_eq!(
+            crate::cbor::from_cbor_slice(&encode(lookalike)).unwrap(),
+            json!({"$$bytes": "0102"})
+        );
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn test_compare_msgpack() {
+        use rmpv::Value as Msgpack;
+
+        let encode = |value: Msgpack| {
+            let mut out = Vec::new();
+            rmpv::encode::write_value(&mut out, &value).unwrap();
+            out
+        };
+
+        let v1 = encode(Msgpack::Map(vec![
+            (Msgpack::from("name"), Msgpack::from("a")),
+            (Msgpack::from("blob"), Msgpack::Binary(vec![0xde, 0xad])),
+        ]));
+        let v2 = encode(Msgpack::Map(vec![
+            (Msgpack::from("name"), Msgpack::from("a")),
+            (Msgpack::from("blob"), Msgpack::from("dead")),
+        ]));
+
+        let diffs = crate::compare_msgpack(&v1, &v2).unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].path, "blob");
+        assert_eq!(diffs[0].old_value, Some(json!({"$bytes": "dead"})));
+        assert_eq!(diffs[0].new_value, Some(json!("dead")));
+
+        assert!(crate::msgpack::from_msgpack_slice(&[0xc0, 0xc0]).is_err());
+
+        let lookalike = encode(Msgpack::Map(vec![(
+            Msgpack::from("$ext"),
+            Msgpack::Map(vec![
+                (Msgpack::from("type"), Msgpack::from(1)),
+                (Msgpack::from("data"), Msgpack::from("ff")),
+            ]),
+        )]));
+        let ext = encode(Msgpack::Ext(1, vec![0xff]));
+        assert!(!crate::compare_msgpack(&lookalike, &ext).unwrap().is_empty());
+    }
+
+    fn stream_diffs(differ: &JsonDiff, json1: &str, json2: &str) -> Result<Vec<String>, String> {
+        let mut paths = Vec::new();
+        differ.diff_readers(json1.as_bytes(), json2.as_bytes(), |diff| {
+            paths.push(format!("{:?} {}", diff.diff_type, diff.path))
+        })?;
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn summary(diffs: &[Diff]) -> Vec<(DiffType, String, Option<String>)> {
+        diffs
+            .iter()
+            .map(|d| (d.diff_type.clone(), d.path.clone(), d.from_path.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_streaming_matches_in_memory_diff() {
+        let json1 = r#"{"a": 1, "b": {"x": [1, 2, {"y": "z"}], "w": true}, "c": [1], "d": null}"#;
+        let json2 =
+            r#"{"b": {"w": false, "x": [1, 3, {"y": "z"}, 4]}, "a": 1, "c": {}, "e": "new"}"#;
+
+        let differ = JsonDiff::new();
+        let streamed = stream_diffs(&differ, json1, json2).unwrap();
+
+        let v1: serde_json::Value = serde_json::from_str(json1).unwrap();
+        let v2: serde_json::Value = serde_json::from_str(json2).unwrap();
+        let mut expected: Vec<String> = differ
+            .diff(&v1, &v2)
+            .iter()
+            .map(|diff| format!("{:?} {}", diff.diff_type, diff.path))
+            .collect();
+        expected.sort();
+
+        assert_eq!(streamed, expected);
+        assert_eq!(
+            streamed,
+            vec![
+                "Added b.x[3]",
+                "Added e",
+                "Modified b.w",
+                "Modified b.x[1]",
+                "Removed d",
+                "TypeChanged { old: Array, new: Object } c"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_streaming_ignore_order_and_errors() {
+        let differ = JsonDiff::new().ignore_order(true);
+        let diffs = stream_diffs(
+            &differ,
+            r#"{"tags": ["a", "b"]}"#,
+            r#"{"tags": ["b", "a"]}"#,
+        );
+        assert!(diffs.unwrap().is_empty());
+
+        let differ = JsonDiff::new();
+        assert!(stream_diffs(&differ, r#"[1, 2,]"#, "[1, 2]").is_err());
+        assert!(stream_diffs(&differ, r#"{"a": 1} x"#, r#"{"a": 1}"#).is_err());
+        assert!(stream_diffs(&differ, r#"{"a" 1}"#, r#"{"a": 1}"#).is_err());
+        assert!(stream_diffs(&differ, r#""\u00e9""#, r#""é""#)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_streaming_deeply_nested_input() {
+        let depth = 10_000;
+        let json1 = format!("{}1{}", "[".repeat(depth), "]".repeat(depth));
+        let json2 = format!("{}2{}", "[".repeat(depth), "]".repeat(depth));
+
+        let handle = std::thread::Builder::new()
+            .stack_size(2 * 1024 * 1024)
+            .spawn(move || stream_diffs(&JsonDiff::new(), &json1, &json2))
+            .unwrap();
+        let diffs = handle.join().unwrap().unwrap();
+        assert_eq!(diffs, vec![format!("Modified {}", "[0]".repeat(depth))]);
+    }
+
+    #[test]
+    fn test_streaming_nesting_bound() {
+        let json = format!("{}{}", "[".repeat(100_001), "]".repeat(100_001));
+        let error = stream_diffs(&JsonDiff::new(), &json, &json).unwrap_err();
+        assert!(error.starts_with("nesting too deep"), "{}", error);
+
+        let json = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        assert!(stream_diffs(&JsonDiff::new(), &json, &json)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_streaming_limits() {
+        let v1: serde_json::Value = (0..100).map(|i| json!({"n": i})).collect();
+        let v2: serde_json::Value = (0..100).map(|i| json!({"n": i + 1})).collect();
+        let (json1, json2) = (v1.to_string(), v2.to_string());
+        let stream = |differ: &JsonDiff, json2: &str| {
+            let mut paths = Vec::new();
+            let stopped_by = differ
+                .diff_readers(json1.as_bytes(), json2.as_bytes(), |diff| {
+                    paths.push(diff.path)
+                })
+                .unwrap();
+            (paths, stopped_by)
+        };
+
+        let (paths, stopped_by) = stream(&JsonDiff::new(), &json2);
+        assert_eq!(paths.len(), 100);
+        assert_eq!(stopped_by, None);
+
+        for differ in [JsonDiff::new().max_diffs(10), JsonDiff::new().max_nodes(21)] {
+            let report = differ.diff_report(&v1, &v2);
+            let (paths, stopped_by) = stream(&differ, &json2);
+            assert_eq!(paths.len(), 10);
+            assert_eq!(
+                paths,
+                report
+                    .diffs
+                    .iter()
+                    .map(|d| d.path.clone())
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(stopped_by, report.stopped_by);
+        }
+
+        let (paths, stopped_by) = stream(&JsonDiff::new().deadline(Instant::now()), &json2);
+        assert!(paths.is_empty());
+        assert_eq!(stopped_by, Some(Limit::Deadline));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let (paths, stopped_by) = stream(&JsonDiff::new().cancellation(token), &json2);
+        assert!(paths.is_empty());
+        assert_eq!(stopped_by, Some(Limit::Cancelled));
+
+        // Input after the point where a limit stopped the walk is not read.
+        let (paths, stopped_by) = stream(&JsonDiff::new().max_diffs(1), &format!("{} x", json2));
+        assert_eq!(paths, vec!["[0].n"]);
+        assert_eq!(stopped_by, Some(Limit::Diffs));
+    }
+
+    #[test]
+    fn test_diff_with_closure_stops_early() {
+        let v1 = json!({"a": 1, "b": 2, "c": 3, "d": 4});
+        let v2 = json!({"a": 10, "b": 20, "c": 30, "d": 40});
+
+        let mut seen = Vec::new();
+        let flow = JsonDiff::new().diff_with(&v1, &v2, &mut |diff: DiffRef| {
+            seen.push(diff.path);
+            if seen.len() == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+
+        assert!(flow.is_break());
+        assert_eq!(seen, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_diff_with_custom_sink() {
+        struct AddedCounter(usize);
+
+        impl<'a> DiffSink<'a> for AddedCounter {
+            fn on_diff(&mut self, diff: DiffRef<'a>) -> ControlFlow<()> {
+                if diff.new_value.is_some() && diff.old_value.is_none() {
+                    self.0 += 1;
+                }
+                ControlFlow::Continue(())
+            }
+        }
+
+        let v1 = json!({"a": 1, "list": [1]});
+        let v2 = json!({"a": 2, "b": 3, "list": [1, 2, 3]});
+
+        let mut counter = AddedCounter(0);
+        let flow = JsonDiff::new().diff_with(&v1, &v2, &mut counter);
+        assert!(flow.is_continue());
+        assert_eq!(counter.0, 3);
+    }
+
+    #[test]
+    fn test_diff_ref_borrows_inputs() {
+        let v1 = json!({"keep": 1, "big": {"nested": [1, 2, 3]}});
+        let v2 = json!({"keep": 2});
+
+        let diffs = JsonDiff::new().diff_ref(&v1, &v2);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].path, "big");
+        assert!(std::ptr::eq(diffs[0].old_value.unwrap(), &v1["big"]));
+
+        let owned: Vec<_> = diffs.iter().map(DiffRef::to_owned).collect();
+        let expected = JsonDiff::new().diff(&v1, &v2);
+        assert_eq!(owned.len(), expected.len());
+        for (a, b) in owned.iter().zip(expected.iter()) {
+            assert_eq!(a.to_string(), b.to_string());
+            assert_eq!(a.borrowed(), b.borrowed());
+        }
+    }
+
+    #[test]
+    fn test_is_equal() {
+        let differ = JsonDiff::new();
+        assert!(differ.is_equal(&json!({"a": [1, 2]}), &json!({"a": [1, 2]})));
+        assert!(!differ.is_equal(&json!({"a": [1, 2]}), &json!({"a": [2, 1]})));
+
+        let differ = JsonDiff::new().ignore_order(true);
+        assert!(differ.is_equal(&json!({"a": [1, 2]}), &json!({"a": [2, 1]})));
+        assert!(!differ.is_equal(&json!({"a": [1, 2]}), &json!({"a": [2, 3]})));
+    }
+
+    #[test]
+    fn test_max_diffs() {
+        let v1 = json!([1, 2, 3, 4, 5]);
+        let v2 = json!([0, 0, 0, 0, 0]);
+
+        let diffs = JsonDiff::new().max_diffs(2).diff(&v1, &v2);
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].path, "[0]");
+        assert_eq!(diffs[1].path, "[1]");
+
+        let diffs = JsonDiff::new().max_diffs(10).diff(&v1, &v2);
+        assert_eq!(diffs.len(), 5);
+
+        let flow = JsonDiff::new()
+            .max_diffs(6)
+            .diff_with(&v1, &v2, &mut Vec::<DiffRef>::new());
+        assert!(flow.is_continue());
+
+        let flow = JsonDiff::new()
+            .max_diffs(5)
+            .diff_with(&v1, &v2, &mut Vec::<DiffRef>::new());
+        assert!(flow.is_break());
+
+        // The second diff is the last one delivered, so nothing after it
+        // is compared.
+        use std::sync::{Arc, Mutex};
+        let visited = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&visited);
+        let flow = JsonDiff::new()
+            .max_diffs(2)
+            .comparator_for(JsonType::Number, move |path: &str, _: &Value, _: &Value| {
+                seen.lock().unwrap().push(path.to_string());
+                Comparison::Defer
+            })
+            .diff_with(&v1, &v2, &mut Vec::<DiffRef>::new());
+        assert!(flow.is_break());
+        assert_eq!(*visited.lock().unwrap(), vec!["[0]", "[1]"]);
+
+        let flow = JsonDiff::new()
+            .max_diffs(1)
+            .diff_with(&v1, &v2, &mut Vec::<DiffRef>::new());
+        assert!(flow.is_break());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let v1: serde_json::Value = (0..500)
+            .map(|i| json!({"id": i, "tags": [i % 3, i % 5]}))
+            .collect();
+        let mut v2 = v1.clone();
+        for i in (0..500).step_by(7) {
+            v2[i]["tags"][1] = json!(-1);
+        }
+        v2.as_array_mut().unwrap().push(json!({"id": 500}));
+
+        let sequential = JsonDiff::new()
+            .parallel_threshold(usize::MAX)
+            .diff(&v1, &v2);
+        let parallel = JsonDiff::new().parallel_threshold(2).diff(&v1, &v2);
+        assert_eq!(parallel.len(), 73);
+        let paths =
+            |diffs: &[crate::Diff]| diffs.iter().map(|d| d.path.clone()).collect::<Vec<_>>();
+        assert_eq!(paths(&parallel), paths(&sequential));
+
+        let limited = JsonDiff::new()
+            .parallel_threshold(2)
+            .max_diffs(3)
+            .diff(&v1, &v2);
+        assert_eq!(paths(&limited), paths(&sequential[..3]));
+
+        let report = JsonDiff::new()
+            .parallel_threshold(2)
+            .max_nodes(1000)
+            .diff_report(&v1, &v2);
+        assert!(report.is_truncated());
+        assert!(paths(&sequential).starts_with(&paths(&report.diffs)));
+    }
+
+    fn nested(depth: usize, leaf: serde_json::Value) -> serde_json::Value {
+        let mut value = leaf;
+        for i in 0..depth {
+            value = if i % 2 == 0 {
+                serde_json::Value::Array(vec![value])
+            } else {
+                serde_json::Value::Object(std::iter::once(("a".to_string(), value)).collect())
+            };
+        }
+        value
+    }
+
+    // serde_json drops values recursively, so unnest by hand.
+    fn drop_nested(mut value: serde_json::Value) {
+        loop {
+            value = match value {
+                serde_json::Value::Array(mut items) => match items.pop() {
+                    Some(item) => item,
+                    None => return,
+                },
+                serde_json::Value::Object(mut map) => match map.remove("a") {
+                    Some(item) => item,
+                    None => return,
+                },
+                _ => return,
+            };
+        }
+    }
+
+    #[test]
+    fn test_deeply_nested_input() {
+        let v1 = nested(10_000, json!(1));
+        let v2 = nested(10_000, json!(2));
+
+        let diffs = JsonDiff::new().diff(&v1, &v2);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].new_value, Some(json!(2)));
+        assert!(diffs[0].path.ends_with(".a[0]"));
+
+        let differ = JsonDiff::new().ignore_order(true);
+        assert!(!differ.is_equal(&v1, &v2));
+        assert!(differ.is_equal(&v1, &v1));
+        assert_eq!(differ.diff_ref(&v1, &v2).len(), 2);
+
+        drop_nested(v1);
+        drop_nested(v2);
+    }
+
+    #[test]
+    fn test_deeply_nested_changed_subtree() {
+        // The changed value is the deep subtree itself, so every owned diff
+        // carries a copy of it.
+        let handle = std::thread::Builder::new()
+            .stack_size(2 * 1024 * 1024)
+            .spawn(|| {
+                let v1 = nested(100_000, json!(1));
+                let differ = JsonDiff::new();
+
+                let mut diffs = differ.diff(&v1, &json!({}));
+                diffs.extend(differ.diff_report(&v1, &json!(null)).diffs);
+                let summary: Vec<_> = diffs
+                    .iter()
+                    .map(|d| (d.path.clone(), d.diff_type.clone()))
+                    .collect();
+                for diff in diffs {
+                    diff.old_value.into_iter().for_each(drop_nested);
+                    diff.new_value.into_iter().for_each(drop_nested);
+                }
+                drop_nested(v1);
+                summary
+            })
+            .unwrap();
+        let summary = handle.join().unwrap();
+        assert_eq!(
+            summary,
+            vec![
+                ("a".to_string(), DiffType::Removed),
+                (
+                    String::new(),
+                    DiffType::TypeChanged {
+                        old: JsonType::Object,
+                        new: JsonType::Null
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested_equality_and_similarity() {
+        // Frames share one path buffer, so the cost of a walk grows linearly
+        // with depth, including the probe behind a truncated subtree.
+        let v1 = nested(100_000, json!(1));
+        let v2 = nested(100_000, json!(2));
+
+        let differ = JsonDiff::new();
+        assert!(!differ.is_equal(&v1, &v2));
+        assert!(differ.is_equal(&v1, &v1));
+        assert_eq!(differ.similarity(&v1, &v2), 1.0 - 2.0 / 200_002.0);
+
+        let diffs = JsonDiff::new().max_depth(8).diff_ref(&v1, &v2);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].diff_type, DiffType::Truncated);
+        assert_eq!(diffs[0].path, "a[0].a[0].a[0].a[0]");
+
+        drop_nested(v1);
+        drop_nested(v2);
+    }
+
+    #[test]
+    fn test_max_depth_truncates() {
+        let v1 = json!({"a": {"b": {"c": 1}}, "x": [1, [2]], "same": {"deep": [1]}});
+        let v2 = json!({"a": {"b": {"c": 2}}, "x": [2, [3]], "same": {"deep": [1]}});
+
+        let diffs = JsonDiff::new().max_depth(1).diff(&v1, &v2);
+        let summary: Vec<_> = diffs
+            .iter()
+            .map(|d| (d.path.as_str(), d.diff_type.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("a", DiffType::Truncated), ("x", DiffType::Truncated),]
+        );
+        assert_eq!(diffs[0].old_value, Some(json!({"b": {"c": 1}})));
+
+        let diffs = JsonDiff::new().max_depth(2).diff(&v1, &v2);
+        let paths: Vec<_> = diffs.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, vec!["a.b", "x[0]", "x[1]"]);
+        assert_eq!(diffs[1].diff_type, DiffType::Modified);
+        assert_eq!(diffs[2].diff_type, DiffType::Truncated);
+
+        assert!(JsonDiff::new().max_depth(0).is_equal(&v1, &v1));
+
+        for depth in 0..4 {
+            let differ = JsonDiff::new().max_depth(depth);
+            let mut expected: Vec<String> = differ
+                .diff(&v1, &v2)
+                .iter()
+                .map(|diff| format!("{:?} {}", diff.diff_type, diff.path))
+                .collect();
+            expected.sort();
+            let streamed = stream_diffs(&differ, &v1.to_string(), &v2.to_string()).unwrap();
+            assert_eq!(streamed, expected);
+        }
+        let differ = JsonDiff::new().max_depth(1);
+        let streamed = stream_diffs(
+            &differ,
+            r#"{"a": {"b": {"c": 1}}}"#,
+            r#"{"a": {"b": {"c": 2}}}"#,
+        );
+        assert_eq!(streamed.unwrap(), vec!["Truncated a"]);
+    }
+
+    #[test]
+    fn test_diff_report_limits() {
+        let v1: serde_json::Value = (0..100).map(|i| json!({"n": i})).collect();
+        let v2: serde_json::Value = (0..100).map(|i| json!({"n": i + 1})).collect();
+
+        let report = JsonDiff::new().diff_report(&v1, &v2);
+        assert_eq!(report.diffs.len(), 100);
+        assert!(!report.is_truncated());
+
+        let report = JsonDiff::new().max_diffs(10).diff_report(&v1, &v2);
+        assert_eq!(report.diffs.len(), 10);
+        assert_eq!(report.stopped_by, Some(Limit::Diffs));
+
+        // The root array, then one object and one number per element.
+        let report = JsonDiff::new().max_nodes(21).diff_report(&v1, &v2);
+        assert_eq!(report.diffs.len(), 10);
+        assert_eq!(report.stopped_by, Some(Limit::Nodes));
+
+        let report = JsonDiff::new()
+            .deadline(Instant::now())
+            .diff_report(&v1, &v2);
+        assert!(report.diffs.is_empty());
+        assert_eq!(report.stopped_by, Some(Limit::Deadline));
+
+        let token = CancellationToken::new();
+        let differ = JsonDiff::new().cancellation(token.clone());
+        assert!(!differ.diff_report(&v1, &v2).is_truncated());
+        token.cancel();
+        let report = differ.diff_report(&v1, &v2);
+        assert!(report.diffs.is_empty());
+        assert_eq!(report.stopped_by, Some(Limit::Cancelled));
+        assert!(differ
+            .diff_with(&v1, &v2, &mut Vec::<DiffRef>::new())
+            .is_break());
+        assert!(!differ.is_equal(&v1, &v2));
+    }
+
+    #[test]
+    fn test_progress_observer() {
+        use std::sync::{Arc, Mutex};
+
+        let v1: serde_json::Value = (0..1000).map(|i| json!({"n": i})).collect();
+        let mut v2 = v1.clone();
+        v2[500]["n"] = json!(-1);
+        v2.as_array_mut().unwrap().push(json!({"n": 1000}));
+
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&updates);
+        let differ = JsonDiff::new().progress(move |progress: Progress| {
+            sink.lock().unwrap().push(progress);
+        });
+        assert_eq!(differ.diff(&v1, &v2).len(), 2);
+
+        let recorded = updates.lock().unwrap().clone();
+        assert!(recorded.len() > 2);
+        assert!(recorded.windows(2).all(|w| w[0].nodes <= w[1].nodes));
+        let last = recorded.last().unwrap();
+        assert_eq!(last.nodes, 2001);
+        assert_eq!(last.estimated_total, Some(2001));
+        assert_eq!(last.fraction(), Some(1.0));
+
+        // The size of a stream is not known, so there is no estimate.
+        updates.lock().unwrap().clear();
+        let (json1, json2) = (v1.to_string(), v2.to_string());
+        differ
+            .diff_readers(json1.as_bytes(), json2.as_bytes(), |_| {})
+            .unwrap();
+        let last = *updates.lock().unwrap().last().unwrap();
+        assert_eq!(last.nodes, 2001);
+        assert_eq!(last.estimated_total, None);
+        assert_eq!(last.fraction(), None);
+    }
+
+    #[test]
+    fn test_prepared_documents() {
+        let base = json!({
+            "users": [{"id": 1, "tags": ["a", "b"]}, {"id": 2, "tags": []}],
+            "meta": {"version": 1, "owner": "x"}
+        });
+        let mut target = PreparedDocument::new(base.clone());
+        let baseline = PreparedDocument::new(base);
+        assert!(JsonDiff::new().diff_prepared(&baseline, &target).is_empty());
+
+        assert_eq!(
+            target.set("/meta/version", json!(2)).unwrap(),
+            Some(json!(1))
+        );
+        target.set("/users/0/tags/-", json!("c")).unwrap();
+        target.set("/meta/a~1b", json!(null)).unwrap();
+        assert_eq!(
+            target.remove("/users/1").unwrap(),
+            json!({"id": 2, "tags": []})
+        );
+        assert!(target.remove("/users/5").is_err());
+        assert!(target.set("/meta/version/x", json!(1)).is_err());
+        assert!(target.set("meta", json!(1)).is_err());
+
+        let rebuilt = PreparedDocument::new(target.value().clone());
+        assert_eq!(target.root().hash, rebuilt.root().hash);
+
+        assert_eq!(JsonDiff::new().diff_prepared(&baseline, &target).len(), 4);
+        for differ in [JsonDiff::new(), JsonDiff::new().ignore_order(true)] {
+            let prepared = differ.diff_prepared(&baseline, &target);
+            let plain = differ.diff(baseline.value(), target.value());
+            assert_eq!(
+                prepared.iter().map(|d| &d.path).collect::<Vec<_>>(),
+                plain.iter().map(|d| &d.path).collect::<Vec<_>>()
+            );
+        }
+
+        target.remove("/meta/a~1b").unwrap();
+        target.set("/meta/version", json!(1)).unwrap();
+        target
+            .set("/users/-", json!({"id": 2, "tags": []}))
+            .unwrap();
+        target.remove("/users/0/tags/2").unwrap();
+        assert_eq!(target.root().hash, baseline.root().hash);
+        assert!(JsonDiff::new().diff_prepared(&baseline, &target).is_empty());
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let mut value: serde_json::Value = serde_json::from_str(
+            r#"{"string":"\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+                "literals":[null,true,false]}"#,
+        )
+        .unwrap();
+        // Built from literals, since serde_json's default float parser is not
+        // exact in the last digit.
+        value["numbers"] = json!([
+            333333333.3333333,
+            1E30,
+            4.50,
+            2e-3,
+            0.000000000000000000000000001
+        ]);
+        assert_eq!(
+            canonicalize(&value).unwrap(),
+            r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#
+        );
+
+        let numbers = json!([0.0, -0.0, 1e21, 1e20, 1e-6, 1e-7, -1.5, 100, 123e-20]);
+        assert_eq!(
+            canonicalize(&numbers).unwrap(),
+            "[0,0,1e+21,100000000000000000000,0.000001,1e-7,-1.5,100,1.23e-18]"
+        );
+
+        let keys = json!({"\u{20ac}": 1, "\r": 2, "\u{1f600}": 3, "\u{fb33}": 4});
+        assert_eq!(
+            canonicalize(&keys).unwrap(),
+            "{\"\\r\":2,\"€\":1,\"\u{1f600}\":3,\"\u{fb33}\":4}"
+        );
+
+        // Only arbitrary precision numbers can be out of range for a double.
+        #[cfg(feature = "arbitrary_precision")]
+        {
+            let huge: serde_json::Value = serde_json::from_str("[1e400]").unwrap();
+            assert!(canonicalize(&huge).unwrap_err().contains("out of range"));
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_matches_diff_equality() {
+        let pairs = [
+            (
+                json!({"a": [1, {"b": null}]}),
+                json!({"a": [1, {"b": null}]}),
+            ),
+            (json!({"a": 1}), json!({"a": 2})),
+            (json!([1, 2]), json!([2, 1])),
+            (json!(1), json!(1.0)),
+            (json!(0.0), json!(-0.0)),
+            (json!({"a": "x"}), json!({"a": ["x"]})),
+            (json!(["ab", "c"]), json!(["a", "bc"])),
+        ];
+        for (v1, v2) in &pairs {
+            let same = fingerprint(v1) == fingerprint(v2);
+            assert_eq!(
+                same,
+                JsonDiff::new().diff(v1, v2).is_empty(),
+                "{} {}",
+                v1,
+                v2
+            );
+        }
+
+        assert_eq!(
+            fingerprint(&json!({"a": [true, "x", null]}))
+                .to_string()
+                .len(),
+            32
+        );
+    }
+
+    #[test]
+    fn test_similarity() {
+        let doc = json!({"id": 1, "items": [{"a": 1, "b": 2}, {"a": 3, "b": 4}]});
+        assert_eq!(similarity(&doc, &doc), 1.0);
+        assert_eq!(similarity(&json!(1), &json!("1")), 0.0);
+        assert_eq!(similarity(&json!({"a": 1}), &json!({"a": 2})), 0.5);
+
+        let mut small_change = doc.clone();
+        small_change["items"][0]["a"] = json!(0);
+        let mut big_change = doc.clone();
+        big_change["items"][0] = json!(null);
+        let small = similarity(&doc, &small_change);
+        let big = similarity(&doc, &big_change);
+        assert!(big < small && small < 1.0);
+
+        let reordered = json!({"id": 1, "items": [{"a": 3, "b": 4}, {"a": 1, "b": 2}]});
+        assert!(similarity(&doc, &reordered) < 1.0);
+        assert_eq!(
+            JsonDiff::new()
+                .ignore_order(true)
+                .similarity(&doc, &reordered),
+            1.0
+        );
+
+        // A renamed key costs one node, on top of the changes in its value.
+        let differ = JsonDiff::new().detect_renames(0.5);
+        let v1 = json!({"a": {"x": 1, "y": 2, "z": 3}});
+        let v2 = json!({"b": {"x": 1, "y": 5, "z": 6}});
+        let score = differ.similarity(&v1, &v2);
+        assert!((0.0..=1.0).contains(&score));
+        assert_eq!(score, 1.0 - 5.0 / 11.0);
+        assert_eq!(differ.similarity(&json!({"a": 1}), &json!({"b": 1})), 0.8);
+        let v1 = json!({"a": 1, "b": 1, "c": 1});
+        let v2 = json!({"x": 2, "y": 2, "z": 2});
+        let score = JsonDiff::new().detect_renames(0.0).similarity(&v1, &v2);
+        assert_eq!(score, 1.0 - 9.0 / 11.0);
+    }
+
+    #[test]
+    fn test_tree_edit_distance() {
+        let costs = EditCosts::default();
+
+        let doc = json!({"a": [1, 2, {"b": null}]});
+        let script = edit_distance(&doc, &doc, &costs);
+        assert_eq!(script.cost, 0.0);
+        assert!(script.diffs.is_empty());
+
+        // A greedy positional diff reports three changes here.
+        let script = edit_distance(&json!([1, 2, 3]), &json!([2, 3]), &costs);
+        assert_eq!(script.cost, 1.0);
+        assert_eq!(
+            summary(&script.diffs),
+            vec![(DiffType::Removed, "[0]".into(), None)]
+        );
+
+        let script = edit_distance(
+            &json!({"a": 1, "b": [2]}),
+            &json!({"a": 1, "c": [2]}),
+            &costs,
+        );
+        assert_eq!(script.cost, 1.0);
+        assert_eq!(
+            summary(&script.diffs),
+            vec![(DiffType::Moved, "c".into(), Some("b".into()))]
+        );
+        assert_eq!(script.diffs[0].to_string(), "Moved: 'b' -> 'c'");
+
+        let script = edit_distance(&json!([1, [2, 3]]), &json!([1, [2, 4], {"x": 5}]), &costs);
+        assert_eq!(script.cost, 4.0);
+        assert_eq!(
+            summary(&script.diffs),
+            vec![
+                (DiffType::Modified, "[1][1]".into(), None),
+                (DiffType::Added, "[2]".into(), None),
+            ]
+        );
+
+        let expensive_rename = EditCosts {
+            rename: 5.0,
+            ..EditCosts::default()
+        };
+        // Deleting a member node hands its value to the object, and the new
+        // member node takes it back, so the value itself is kept.
+        let script = edit_distance(&json!({"b": 1}), &json!({"c": 1}), &expensive_rename);
+        assert_eq!(script.cost, 2.0);
+        assert_eq!(
+            summary(&script.diffs),
+            vec![
+                (DiffType::Removed, "b".into(), None),
+                (DiffType::Added, "c".into(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_detect_renames() {
+        let v1 = json!({"user": {"id": 7, "tags": ["a", "b", "c"], "userName": "ann"}});
+        let v2 = json!({"user": {"id": 7, "labels": ["a", "b", "x"], "username": "ann"}});
+
+        assert_eq!(JsonDiff::new().diff(&v1, &v2).len(), 4);
+
+        let diffs = JsonDiff::new().detect_renames(1.0).diff(&v1, &v2);
+        assert_eq!(
+            summary(&diffs),
+            vec![
+                (DiffType::Removed, "user.tags".into(), None),
+                (DiffType::Added, "user.labels".into(), None),
+                (
+                    DiffType::Moved,
+                    "user.username".into(),
+                    Some("user.userName".into())
+                ),
+            ]
+        );
+        assert_eq!(
+            diffs[2].to_string(),
+            "Moved: 'user.userName' -> 'user.username'"
+        );
+
+        // A looser threshold also pairs values that changed a little, and
+        // reports the change under the new key.
+        let diffs = JsonDiff::new().detect_renames(0.5).diff(&v1, &v2);
+        assert_eq!(
+            summary(&diffs),
+            vec![
+                (
+                    DiffType::Moved,
+                    "user.labels".into(),
+                    Some("user.tags".into())
+                ),
+                (DiffType::Modified, "user.labels[2]".into(), None),
+                (
+                    DiffType::Moved,
+                    "user.username".into(),
+                    Some("user.userName".into())
+                ),
+            ]
+        );
+
+        // Each new key is used at most once, and equality is unaffected.
+        let v1 = json!({"a": 1, "b": 1});
+        let v2 = json!({"c": 1});
+        let diffs = JsonDiff::new().detect_renames(1.0).diff(&v1, &v2);
+        assert_eq!(
+            summary(&diffs),
+            vec![
+                (DiffType::Removed, "b".into(), None),
+                (DiffType::Moved, "c".into(), Some("a".into())),
+            ]
+        );
+        assert!(!JsonDiff::new().detect_renames(1.0).is_equal(&v1, &v2));
+
+        // Candidates are scored with the differ's own options.
+        let differ = JsonDiff::new()
+            .coerce(Coercion::StringNumber)
+            .comparator_at("b.note", |_: &str, _: &Value, _: &Value| Comparison::Equal)
+            .detect_renames(1.0);
+        let diffs = differ.diff(
+            &json!({"a": {"n": "1", "note": "x"}}),
+            &json!({"b": {"n": 1, "note": "y"}}),
+        );
+        assert_eq!(
+            summary(&diffs),
+            vec![(DiffType::Moved, "b".into(), Some("a".into()))]
+        );
+
+        // Scoring candidate pairs counts against the limits.
+        let v1: serde_json::Value = (0..200)
+            .map(|i| (format!("old{}", i), json!([i, i + 1, i + 2])))
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+        let v2: serde_json::Value = (0..200)
+            .map(|i| (format!("new{}", i), json!([i + 1, i + 2, i + 3])))
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+        let report = JsonDiff::new()
+            .detect_renames(0.9)
+            .max_nodes(100)
+            .diff_report(&v1, &v2);
+        assert_eq!(report.stopped_by, Some(Limit::Nodes));
+        assert!(report.diffs.is_empty());
+    }
+
+    #[test]
+    fn test_detect_copies() {
+        let v1 = json!({
+            "base": {"port": 80, "tags": ["a"]},
+            "list": [{"id": 1}]
+        });
+        let v2 = json!({
+            "base": {"port": 80, "tags": ["a"]},
+            "extra": {},
+            "list": [{"id": 1}, {"id": 1}, {"id": 2}],
+            "prod": {"port": 80, "tags": ["a"]},
+            "size": 80
+        });
+
+        let differ = JsonDiff::new().detect_copies(true);
+        let diffs = differ.diff(&v1, &v2);
+        assert_eq!(
+            summary(&diffs),
+            vec![
+                (DiffType::Copied, "list[1]".into(), Some("list[0]".into())),
+                (DiffType::Added, "list[2]".into(), None),
+                (DiffType::Added, "extra".into(), None),
+                (DiffType::Copied, "prod".into(), Some("base".into())),
+                (DiffType::Added, "size".into(), None),
+            ]
+        );
+        assert_eq!(diffs[3].old_value, Some(v1["base"].clone()));
+        assert_eq!(diffs[3].to_string(), "Copied: 'base' -> 'prod'");
+        assert_eq!(differ.diff_ref(&v1, &v2).len(), 5);
+
+        let report = DiffFormatter::new().format_json(&diffs);
+        let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+        assert_eq!(report["summary"]["copied"], 2);
+        assert_eq!(report["diffs"][3]["from_path"], "base");
+
+        assert!(JsonDiff::new()
+            .diff(&v1, &v2)
+            .iter()
+            .all(|d| d.diff_type == DiffType::Added));
+
+        // Sources are found by the differ's own equality, not only by
+        // identical structure.
+        let v1 = json!({"src": [1, 2, 3], "ids": {"n": "7"}});
+        let v2 = json!({"src": [1, 2, 3], "ids": {"n": "7"}, "dst": [3, 2, 1], "more": {"n": 7}});
+        let diffs = JsonDiff::new()
+            .ignore_order(true)
+            .coerce(Coercion::StringNumber)
+            .detect_copies(true)
+            .diff(&v1, &v2);
+        assert_eq!(
+            summary(&diffs),
+            vec![
+                (DiffType::Copied, "dst".into(), Some("src".into())),
+                (DiffType::Copied, "more".into(), Some("ids".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_changed() {
+        let v1 = json!({"count": "5", "meta": {"a": 1}, "n": 1, "flag": true});
+        let v2 = json!({"count": 5, "meta": null, "n": 1.5, "flag": false});
+
+        let diffs = compare_values(&v1, &v2);
+        let changes: Vec<_> = diffs
+            .iter()
+            .map(|d| (d.path.as_str(), d.diff_type.clone()))
+            .collect();
+        assert_eq!(changes.len(), 4);
+        assert!(changes.contains(&(
+            "count",
+            DiffType::TypeChanged {
+                old: JsonType::String,
+                new: JsonType::Number
+            }
+        )));
+        assert!(changes.contains(&(
+            "meta",
+            DiffType::TypeChanged {
+                old: JsonType::Object,
+                new: JsonType::Null
+            }
+        )));
+        // Integers and floats are both numbers.
+        assert!(changes.contains(&("n", DiffType::Modified)));
+        assert!(changes.contains(&("flag", DiffType::Modified)));
+
+        let count = diffs.iter().find(|d| d.path == "count").unwrap();
+        assert_eq!(
+            count.to_string(),
+            "Type changed at 'count' from string to number: \
+             Some(String(\"5\")) -> Some(Number(5))"
+        );
+
+        let report = DiffFormatter::new().format_json(&diffs);
+        let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+        assert_eq!(report["summary"]["type_changed"], 2);
+        assert_eq!(report["summary"]["modified"], 2);
+        let count = report["diffs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|d| d["path"] == "count")
+            .unwrap();
+        assert_eq!(count["type"], "TypeChanged");
+        assert_eq!(count["old_type"], "string");
+        assert_eq!(count["new_type"], "number");
+    }
+
+    #[test]
+    fn test_path_pattern() {
+        let pattern = PathPattern::new("users[*].id");
+        assert!(pattern.matches("users[0].id"));
+        assert!(pattern.matches("users[12].id"));
+        assert!(!pattern.matches("users.admin.id"));
+        assert!(!pattern.matches("users[0].id.x"));
+
+        let pattern = PathPattern::new("**.updated_at");
+        assert!(pattern.matches("updated_at"));
+        assert!(pattern.matches("a[3].b.updated_at"));
+        assert!(!pattern.matches("a.updated_at_ms"));
+
+        assert!(PathPattern::new("a.*.c").matches("a.b.c"));
+        assert!(!PathPattern::new("a.*.c").matches("a[0].c"));
+        assert!(PathPattern::new("a[1]").matches("a[1]"));
+        assert!(!PathPattern::new("a[1]").matches("a[2]"));
+        assert!(PathPattern::new("").matches(""));
+        assert!(!PathPattern::new("").matches("a"));
+        assert!(PathPattern::new("**").matches("a.b[0]"));
+    }
+
+    #[test]
+    fn test_coercion() {
+        let v1 = json!({
+            "id": "42",
+            "active": "true",
+            "created": 1700000000,
+            "nested": {"id": "7"},
+            "ratio": "0.5"
+        });
+        let v2 = json!({
+            "id": 42,
+            "active": true,
+            "created": "2023-11-14T22:13:20+01:00",
+            "nested": {"id": 7},
+            "ratio": 0.25
+        });
+        assert_eq!(compare_values(&v1, &v2).len(), 5);
+
+        let differ = JsonDiff::new()
+            .coerce(Coercion::StringNumber)
+            .coerce_at("active", Coercion::StringBool)
+            .coerce_at("**.created", Coercion::EpochTimestamp);
+        let diffs = differ.diff(&v1, &v2);
+        let paths: Vec<_> = diffs.iter().map(|d| d.path.as_str()).collect();
+        // The timestamp is an hour before the epoch value.
+        assert_eq!(paths.len(), 2);
+        assert!(paths.contains(&"created"));
+        assert!(paths.contains(&"ratio"));
+
+        let v2 = json!({
+            "id": 42,
+            "active": true,
+            "created": "2023-11-14T23:13:20+01:00",
+            "nested": {"id": 7},
+            "ratio": 0.5
+        });
+        assert!(differ.is_equal(&v1, &v2));
+        assert!(!JsonDiff::new()
+            .coerce_at("id", Coercion::StringNumber)
+            .is_equal(&v1, &v2));
+
+        let differ = differ.report_coercions(true);
+        assert!(differ.is_equal(&v1, &v2));
+        assert_eq!(differ.similarity(&v1, &v2), 1.0);
+        let mut coerced: Vec<_> = differ
+            .diff(&v1, &v2)
+            .iter()
+            .map(|d| (d.path.clone(), d.diff_type.clone()))
+            .collect();
+        coerced.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            coerced,
+            vec![
+                ("active".into(), DiffType::Coerced(Coercion::StringBool)),
+                (
+                    "created".into(),
+                    DiffType::Coerced(Coercion::EpochTimestamp)
+                ),
+                ("id".into(), DiffType::Coerced(Coercion::StringNumber)),
+                (
+                    "nested.id".into(),
+                    DiffType::Coerced(Coercion::StringNumber)
+                ),
+                ("ratio".into(), DiffType::Coerced(Coercion::StringNumber)),
+            ]
+        );
+
+        // Fractional epochs and timestamps are compared to the nanosecond.
+        let differ = JsonDiff::new().coerce(Coercion::EpochTimestamp);
+        assert!(differ.is_equal(&json!(1.5), &json!("1970-01-01T00:00:01.5Z")));
+        assert!(!differ.is_equal(&json!(1.5), &json!("1970-01-01T00:00:01Z")));
+        assert!(!differ.is_equal(&json!(0), &json!("not a date")));
+        assert!(!JsonDiff::new()
+            .coerce(Coercion::StringBool)
+            .is_equal(&json!("TRUE"), &json!(true)));
+    }
+
+    #[test]
+    fn test_null_empty_missing_equivalence() {
+        let empty = json!({});
+        let null = json!({"a": null});
+        let array = json!({"a": []});
+        let object = json!({"a": {}});
+        for (v1, v2) in [(&null, &empty), (&array, &empty), (&array, &null)] {
+            assert_eq!(compare_values(v1, v2).len(), 1);
+        }
+
+        let differ = JsonDiff::new().equivalence(Equivalence::NullIsMissing);
+        assert!(differ.is_equal(&null, &empty));
+        assert!(differ.is_equal(&empty, &null));
+        assert!(!differ.is_equal(&array, &empty));
+        assert!(!differ.is_equal(&array, &null));
+
+        let differ = JsonDiff::new().equivalence(Equivalence::EmptyIsNull);
+        assert!(differ.is_equal(&array, &null));
+        assert!(differ.is_equal(&array, &object));
+        assert!(!differ.is_equal(&array, &empty));
+        assert!(!differ.is_equal(&json!([]), &json!([null])));
+
+        let differ = JsonDiff::new()
+            .equivalence(Equivalence::NullIsMissing)
+            .equivalence(Equivalence::EmptyIsNull);
+        for v1 in [&empty, &null, &array, &object] {
+            for v2 in [&empty, &null, &array, &object] {
+                assert!(differ.is_equal(v1, v2), "{} vs {}", v1, v2);
+            }
+        }
+        assert!(!differ.is_equal(&json!({"a": [0]}), &empty));
+
+        // Per path, and the same when streaming.
+        let v1 = json!({"meta": {"note": null}, "tags": null});
+        let v2 = json!({"meta": {}});
+        let differ = JsonDiff::new().equivalence_at("meta.*", Equivalence::NullIsMissing);
+        let diffs = differ.diff(&v1, &v2);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].path, "tags");
+        let streamed = stream_diffs(&differ, &v1.to_string(), &v2.to_string()).unwrap();
+        assert_eq!(streamed, vec!["Removed tags"]);
+    }
+
+    #[test]
+    fn test_defaults() {
+        let sparse = json!({"server": {"port": 8080}});
+        let expanded = json!({
+            "server": {"host": "localhost", "port": 8080, "tls": {"enabled": false}},
+            "workers": [{"name": "a", "retries": 3}, {"name": "b", "retries": 5}]
+        });
+        let sparse_workers = json!({
+            "server": {"port": 8080},
+            "workers": [{"name": "a"}, {"name": "b"}]
+        });
+
+        let differ = JsonDiff::new()
+            .defaults(json!({
+                "server": {"host": "localhost", "port": 80, "tls": {"enabled": false}},
+                "workers": []
+            }))
+            .default_at("workers[*].retries", json!(3));
+
+        // Only values that differ from the defaults are reported.
+        let diffs = differ.diff(&sparse_workers, &expanded);
+        let paths: Vec<_> = diffs.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, vec!["workers[1].retries"]);
+        assert_eq!(compare_values(&sparse_workers, &expanded).len(), 4);
+
+        // A missing object is compared to its default, member by member.
+        let diffs = differ.diff(&sparse, &expanded);
+        let paths: Vec<_> = diffs.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, vec!["workers"]);
+        assert!(differ.is_equal(&json!({}), &json!({"server": {"tls": {}}})));
+        assert!(!differ.is_equal(&json!({}), &json!({"server": {"port": 8080}})));
+        assert!(differ.is_equal(&json!({"server": {"port": 80}}), &json!({})));
+
+        let streamed =
+            stream_diffs(&differ, &sparse_workers.to_string(), &expanded.to_string()).unwrap();
+        assert_eq!(streamed, vec!["Added workers[1].retries"]);
+    }
+
+    #[test]
+    fn test_value_comparators() {
+        struct TrailingSlash;
+
+        impl ValueComparator for TrailingSlash {
+            fn compare(&self, _path: &str, old: &Value, new: &Value) -> Comparison {
+                match (old.as_str(), new.as_str()) {
+                    (Some(a), Some(b)) if a.trim_end_matches('/') == b.trim_end_matches('/') => {
+                        Comparison::Equal
+                    }
+                    _ => Comparison::Defer,
+                }
+            }
+        }
+
+        let v1 = json!({
+            "links": {"home": "https://a.example/", "docs": "https://b.example"},
+            "name": "Widget",
+            "version": {"major": 1, "minor": 2}
+        });
+        let v2 = json!({
+            "links": {"home": "https://a.example", "docs": "https://c.example"},
+            "name": "widget",
+            "version": {"major": 1, "minor": 3}
+        });
+        assert_eq!(compare_values(&v1, &v2).len(), 4);
+
+        let differ = JsonDiff::new()
+            .comparator_at("links.*", TrailingSlash)
+            .comparator_for(
+                JsonType::String,
+                |_: &str, old: &Value, new: &Value| match (old.as_str(), new.as_str()) {
+                    (Some(a), Some(b)) if a.eq_ignore_ascii_case(b) => Comparison::Equal,
+                    _ => Comparison::Defer,
+                },
+            )
+            .comparator_at("version", |_: &str, old: &Value, new: &Value| {
+                if old["major"] == new["major"] {
+                    Comparison::Equal
+                } else {
+                    Comparison::Different
+                }
+            });
+        let diffs = differ.diff(&v1, &v2);
+        let paths: Vec<_> = diffs.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, vec!["links.docs"]);
+        assert_eq!(diffs[0].diff_type, DiffType::Modified);
+
+        // `Different` reports the pair as a whole without descending.
+        let v3 = json!({"links": {}, "name": "WIDGET", "version": {"major": 2, "minor": 2}});
+        let diffs = differ.diff(&v1, &v3);
+        let summary: Vec<_> = diffs
+            .iter()
+            .map(|d| (d.path.as_str(), d.diff_type.clone()))
+            .collect();
+        assert!(summary.contains(&("version", DiffType::Modified)));
+        assert!(!summary.iter().any(|(path, _)| path.starts_with("version.")));
+        assert!(!summary.iter().any(|(path, _)| *path == "name"));
+
+        let streamed = stream_diffs(&differ, &v1.to_string(), &v2.to_string()).unwrap();
+        assert_eq!(streamed, vec!["Modified links.docs"]);
+
+        // Comparators also decide equality while matching unordered arrays.
+        let differ = JsonDiff::new()
+            .ignore_order(true)
+            .comparator_for(JsonType::String, TrailingSlash);
+        assert!(differ.is_equal(&json!(["/a/", "/b"]), &json!(["/b/", "/a"])));
+    }
 }
@@ -0,0 +1,154 @@
+use crate::bytes::{escape_key, tagged};
+use crate::comparator::Comparison;
+use crate::datetime::{civil_from_days, days_from_civil, format_time};
+use ::toml::value::{Datetime, Offset};
+use serde_json::{Map, Number, Value};
+
+const DATETIME_KINDS: [&str; 4] = [
+    "$offset_datetime",
+    "$local_datetime",
+    "$local_date",
+    "$local_time",
+];
+
+/// Parses a TOML document into the JSON data model.
+///
+/// TOML datetimes have no JSON counterpart, so each one becomes a single-key
+/// object naming its kind, e.g. `{"$offset_datetime": "1979-05-27T07:32:00Z"}`.
+/// The four kinds are `$offset_datetime`, `$local_datetime`, `$local_date` and
+/// `$local_time`. Offset datetimes are normalized to UTC, so two spellings of
+/// the same instant compare equal and a plain string never equals a datetime.
+/// Table keys starting with `$` get another `$` prepended, so that a table
+/// such as `{"$local_date" = "1979-05-27"}` stays distinct from a datetime.
+pub fn from_toml_str(input: &str) -> Result<Value, String> {
+    let table: ::toml::Table = input
+        .parse()
+        .map_err(|e: ::toml::de::Error| e.to_string())?;
+    toml_to_value(::toml::Value::Table(table), "")
+}
+
+fn toml_to_value(value: ::toml::Value, path: &str) -> Result<Value, String> {
+    match value {
+        ::toml::Value::String(s) => Ok(Value::String(s)),
+        ::toml::Value::Integer(i) => Ok(Value::Number(i.into())),
+        ::toml::Value::Float(f) => Number::from_f64(f)
+            .map(Value::Number)
+            .ok_or_else(|| format!("float {} at '{}' cannot be represented in JSON", f, path)),
+        ::toml::Value::Boolean(b) => Ok(Value::Bool(b)),
+        ::toml::Value::Datetime(dt) => Ok(datetime_to_value(&dt)),
+        ::toml::Value::Array(items) => items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let item_path = if path.is_empty() {
+                    format!("[{}]", i)
+                } else {
+                    format!("{}[{}]", path, i)
+                };
+                toml_to_value(item, &item_path)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        ::toml::Value::Table(table) => {
+            let mut map = Map::new();
+            for (key, item) in table {
+                let item_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                let item = toml_to_value(item, &item_path)?;
+                map.insert(escape_key(key), item);
+            }
+            Ok(Value::Object(map))
+        }
+    }
+}
+
+fn datetime_to_value(dt: &Datetime) -> Value {
+    let (kind, text) = match (dt.date, dt.time, dt.offset) {
+        (Some(date), Some(time), Some(offset)) => {
+            let offset_minutes = match offset {
+                Offset::Z => 0,
+                Offset::Custom { minutes } => minutes as i64,
+            };
+            let local_minutes =
+                days_from_civil(date.year as i64, date.month as u32, date.day as u32) * 1440
+                    + time.hour as i64 * 60
+                    + time.minute as i64;
+            let utc_minutes = local_minutes - offset_minutes;
+            let (year, month, day) = civil_from_days(utc_minutes.div_euclid(1440));
+            let minute_of_day = utc_minutes.rem_euclid(1440) as u32;
+            let text = format!(
+                "{:04}-{:02}-{:02}T{}Z",
+                year,
+                month,
+                day,
+                format_time(
+                    minute_of_day / 60,
+                    minute_of_day % 60,
+                    time.second as u32,
+                    time.nanosecond
+                )
+            );
+            ("$offset_datetime", text)
+        }
+        (Some(date), Some(time), None) => (
+            "$local_datetime",
+            format!(
+                "{:04}-{:02}-{:02}T{}",
+                date.year,
+                date.month,
+                date.day,
+                format_time(
+                    time.hour as u32,
+                    time.minute as u32,
+                    time.second as u32,
+                    time.nanosecond
+                )
+            ),
+        ),
+        (Some(date), None, _) => (
+            "$local_date",
+            format!("{:04}-{:02}-{:02}", date.year, date.month, date.day),
+        ),
+        (None, Some(time), _) => (
+            "$local_time",
+            format_time(
+                time.hour as u32,
+                time.minute as u32,
+                time.second as u32,
+                time.nanosecond,
+            ),
+        ),
+        (None, None, _) => ("$local_datetime", dt.to_string()),
+    };
+
+    tagged(kind, Value::String(text))
+}
+
+/// Compares datetimes read by [`from_toml_str`] as whole values, so that a
+/// changed datetime is reported at its own path rather than inside its
+/// wrapper object. `compare_toml` registers it with
+/// [`JsonDiff::comparator_for`] for objects; do the same to diff TOML with
+/// other options.
+///
+/// [`JsonDiff::comparator_for`]: crate::JsonDiff::comparator_for
+pub fn compare_datetimes(_path: &str, old: &Value, new: &Value) -> Comparison {
+    if !is_datetime(old) && !is_datetime(new) {
+        Comparison::Defer
+    } else if old == new {
+        Comparison::Equal
+    } else {
+        Comparison::Different
+    }
+}
+
+fn is_datetime(value: &Value) -> bool {
+    match value {
+        Value::Object(map) => {
+            map.len() == 1 && map.keys().all(|key| DATETIME_KINDS.contains(&key.as_str()))
+        }
+        _ => false,
+    }
+}
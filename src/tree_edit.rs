@@ -0,0 +1,379 @@
+use crate::diff::{index_path, key_path, modification, numbers_equal, Diff, DiffType};
+use serde_json::Value;
+
+/// The cost of each kind of edit for [`edit_distance`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EditCosts {
+    /// Adding one node.
+    pub insert: f64,
+    /// Removing one node.
+    pub delete: f64,
+    /// Changing the key of an object member.
+    pub rename: f64,
+    /// Changing a scalar to a different scalar.
+    pub update: f64,
+}
+
+impl Default for EditCosts {
+    fn default() -> Self {
+        Self {
+            insert: 1.0,
+            delete: 1.0,
+            rename: 1.0,
+            update: 1.0,
+        }
+    }
+}
+
+/// A minimal-cost edit script found by [`edit_distance`].
+#[derive(Debug, Clone)]
+pub struct EditScript {
+    pub cost: f64,
+    pub diffs: Vec<Diff>,
+}
+
+/// Computes the ordered tree edit distance between `v1` and `v2` with the
+/// Zhang-Shasha algorithm, together with an edit script that achieves it.
+///
+/// Arrays and objects are nodes whose children are their elements; each
+/// object member is a node labelled with its key, whose only child is the
+/// member's value. A node can only be relabelled into a node of the same
+/// kind: a member into another member (a rename), or a scalar into another
+/// scalar (an update).
+///
+/// Edits become diffs as follows. Renames are `Moved` diffs with the old
+/// member path in `from_path`, updates are `Modified` (or `TypeChanged`
+/// between scalars of different types), and deleted and inserted nodes are
+/// `Removed` and `Added`. A subtree that is deleted or inserted as a whole
+/// is reported once, at its root. `Removed` paths refer to `v1` and all
+/// other paths to `v2`.
+///
+/// This takes O(n·m) memory and up to O(n²·m²) time for documents of n
+/// and m nodes, so it suits small documents where a minimal answer matters;
+/// [`JsonDiff`](crate::JsonDiff) is the tool for everything else.
+pub fn edit_distance(v1: &Value, v2: &Value, costs: &EditCosts) -> EditScript {
+    let t1 = Tree::new(v1);
+    let t2 = Tree::new(v2);
+    let zs = ZhangShasha {
+        t1: &t1,
+        t2: &t2,
+        costs,
+    };
+
+    let (n, m) = (t1.nodes.len(), t2.nodes.len());
+    let mut tree_dist = vec![0.0; n * m];
+    let mut forest = Vec::new();
+    for &i in &t1.keyroots {
+        for &j in &t2.keyroots {
+            zs.forest_dist(i, j, &mut forest, &mut tree_dist, true);
+        }
+    }
+    let cost = tree_dist[n * m - 1];
+
+    // Recover the mapping by retracing the forest distances of the root
+    // pair, and of every subtree pair whose distance was taken from
+    // `tree_dist` along the way.
+    let mut deleted = vec![false; n];
+    let mut inserted = vec![false; m];
+    let mut mapped = Vec::new();
+    let mut pairs = vec![(n - 1, m - 1)];
+    while let Some((i, j)) = pairs.pop() {
+        zs.forest_dist(i, j, &mut forest, &mut tree_dist, false);
+        let (li, lj) = (t1.nodes[i].leftmost, t2.nodes[j].leftmost);
+        let cols = j - lj + 2;
+        let at = |x: usize, y: usize| forest[x * cols + y];
+
+        let (mut x, mut y) = (i - li + 1, j - lj + 1);
+        while x > 0 || y > 0 {
+            if x > 0 && y > 0 {
+                let (nx, ny) = (li + x - 1, lj + y - 1);
+                let (lx, ly) = (t1.nodes[nx].leftmost, t2.nodes[ny].leftmost);
+                if lx == li && ly == lj {
+                    if at(x, y) == at(x - 1, y - 1) + zs.relabel(nx, ny) {
+                        mapped.push((nx, ny));
+                        x -= 1;
+                        y -= 1;
+                        continue;
+                    }
+                } else if at(x, y) == at(lx - li, ly - lj) + tree_dist[nx * m + ny] {
+                    pairs.push((nx, ny));
+                    x = lx - li;
+                    y = ly - lj;
+                    continue;
+                }
+            }
+            if x > 0 && at(x, y) == at(x - 1, y) + costs.delete {
+                deleted[li + x - 1] = true;
+                x -= 1;
+            } else {
+                inserted[lj + y - 1] = true;
+                y -= 1;
+            }
+        }
+    }
+
+    let mut removed: Vec<_> = whole_subtree_roots(&t1, &deleted)
+        .map(|x| {
+            let node = &t1.nodes[x];
+            (
+                node.preorder,
+                Diff {
+                    path: node.path.clone(),
+                    from_path: None,
+                    diff_type: DiffType::Removed,
+                    old_value: Some(node.value.clone()),
+                    new_value: None,
+                },
+            )
+        })
+        .collect();
+    removed.sort_by_key(|(preorder, _)| *preorder);
+
+    let mut changed: Vec<_> = whole_subtree_roots(&t2, &inserted)
+        .map(|y| {
+            let node = &t2.nodes[y];
+            (
+                node.preorder,
+                Diff {
+                    path: node.path.clone(),
+                    from_path: None,
+                    diff_type: DiffType::Added,
+                    old_value: None,
+                    new_value: Some(node.value.clone()),
+                },
+            )
+        })
+        .chain(mapped.into_iter().filter_map(|(x, y)| {
+            let (old, new) = (&t1.nodes[x], &t2.nodes[y]);
+            let (diff_type, from_path) = match (&old.label, &new.label) {
+                (Label::Member(k1), Label::Member(k2)) if k1 != k2 => {
+                    (DiffType::Moved, Some(old.path.clone()))
+                }
+                (Label::Scalar(s1), Label::Scalar(s2)) if !scalars_equal(s1, s2) => {
+                    (modification(old.value, new.value), None)
+                }
+                _ => return None,
+            };
+            Some((
+                new.preorder,
+                Diff {
+                    path: new.path.clone(),
+                    from_path,
+                    diff_type,
+                    old_value: Some(old.value.clone()),
+                    new_value: Some(new.value.clone()),
+                },
+            ))
+        }))
+        .collect();
+    changed.sort_by_key(|(preorder, _)| *preorder);
+
+    EditScript {
+        cost,
+        diffs: removed
+            .into_iter()
+            .chain(changed)
+            .map(|(_, diff)| diff)
+            .collect(),
+    }
+}
+
+struct ZhangShasha<'t, 'a> {
+    t1: &'t Tree<'a>,
+    t2: &'t Tree<'a>,
+    costs: &'t EditCosts,
+}
+
+impl ZhangShasha<'_, '_> {
+    fn relabel(&self, x: usize, y: usize) -> f64 {
+        match (&self.t1.nodes[x].label, &self.t2.nodes[y].label) {
+            (Label::Array, Label::Array) | (Label::Object, Label::Object) => 0.0,
+            (Label::Member(k1), Label::Member(k2)) if k1 == k2 => 0.0,
+            (Label::Member(_), Label::Member(_)) => self.costs.rename,
+            (Label::Scalar(s1), Label::Scalar(s2)) if scalars_equal(s1, s2) => 0.0,
+            (Label::Scalar(_), Label::Scalar(_)) => self.costs.update,
+            _ => f64::INFINITY,
+        }
+    }
+
+    // Fills `forest` with the distances between every prefix (in postorder)
+    // of the subtrees rooted at `i` and `j`, as a row-major table with an
+    // extra leading row and column for the empty forest.
+    fn forest_dist(
+        &self,
+        i: usize,
+        j: usize,
+        forest: &mut Vec<f64>,
+        tree_dist: &mut [f64],
+        record: bool,
+    ) {
+        let (li, lj) = (self.t1.nodes[i].leftmost, self.t2.nodes[j].leftmost);
+        let (rows, cols) = (i - li + 2, j - lj + 2);
+        let m = self.t2.nodes.len();
+
+        forest.clear();
+        forest.resize(rows * cols, 0.0);
+        for x in 1..rows {
+            forest[x * cols] = forest[(x - 1) * cols] + self.costs.delete;
+        }
+        for y in 1..cols {
+            forest[y] = forest[y - 1] + self.costs.insert;
+        }
+
+        for x in 1..rows {
+            let nx = li + x - 1;
+            let lx = self.t1.nodes[nx].leftmost;
+            for y in 1..cols {
+                let ny = lj + y - 1;
+                let ly = self.t2.nodes[ny].leftmost;
+                let delete = forest[(x - 1) * cols + y] + self.costs.delete;
+                let insert = forest[x * cols + y - 1] + self.costs.insert;
+                let dist = if lx == li && ly == lj {
+                    let relabel = forest[(x - 1) * cols + y - 1] + self.relabel(nx, ny);
+                    let dist = delete.min(insert).min(relabel);
+                    if record {
+                        tree_dist[nx * m + ny] = dist;
+                    }
+                    dist
+                } else {
+                    let subtree = forest[(lx - li) * cols + (ly - lj)] + tree_dist[nx * m + ny];
+                    delete.min(insert).min(subtree)
+                };
+                forest[x * cols + y] = dist;
+            }
+        }
+    }
+}
+
+enum Label<'a> {
+    Array,
+    Object,
+    Member(&'a str),
+    Scalar(&'a Value),
+}
+
+struct Node<'a> {
+    label: Label<'a>,
+    value: &'a Value,
+    path: String,
+    leftmost: usize,
+    preorder: usize,
+}
+
+/// A document flattened into postorder, with the leftmost leaf of every
+/// subtree and the keyroots Zhang-Shasha iterates over.
+struct Tree<'a> {
+    nodes: Vec<Node<'a>>,
+    keyroots: Vec<usize>,
+}
+
+impl<'a> Tree<'a> {
+    fn new(root: &'a Value) -> Self {
+        enum Task<'a> {
+            Enter(Label<'a>, &'a Value, String),
+            Exit(Label<'a>, &'a Value, String, usize, usize),
+        }
+
+        let mut nodes: Vec<Node<'a>> = Vec::new();
+        let mut preorder = 0;
+        let mut tasks = vec![Task::Enter(label_of(root), root, String::new())];
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Enter(label, value, path) => {
+                    let children: Vec<Task<'a>> = match (&label, value) {
+                        (Label::Member(_), value) => {
+                            vec![Task::Enter(label_of(value), value, path.clone())]
+                        }
+                        (Label::Array, Value::Array(items)) => items
+                            .iter()
+                            .enumerate()
+                            .map(|(i, item)| {
+                                Task::Enter(label_of(item), item, index_path(&path, i))
+                            })
+                            .collect(),
+                        (Label::Object, Value::Object(map)) => map
+                            .iter()
+                            .map(|(key, item)| {
+                                Task::Enter(Label::Member(key), item, key_path(&path, key))
+                            })
+                            .collect(),
+                        _ => Vec::new(),
+                    };
+                    tasks.push(Task::Exit(label, value, path, nodes.len(), preorder));
+                    preorder += 1;
+                    tasks.extend(children.into_iter().rev());
+                }
+                Task::Exit(label, value, path, leftmost, preorder) => nodes.push(Node {
+                    label,
+                    value,
+                    path,
+                    leftmost,
+                    preorder,
+                }),
+            }
+        }
+
+        // A keyroot is the highest node with a given leftmost leaf.
+        let mut seen = vec![false; nodes.len()];
+        let mut keyroots = Vec::new();
+        for (i, node) in nodes.iter().enumerate().rev() {
+            if !seen[node.leftmost] {
+                seen[node.leftmost] = true;
+                keyroots.push(i);
+            }
+        }
+        keyroots.reverse();
+
+        Self { nodes, keyroots }
+    }
+}
+
+fn label_of(value: &Value) -> Label<'_> {
+    match value {
+        Value::Array(_) => Label::Array,
+        Value::Object(_) => Label::Object,
+        scalar => Label::Scalar(scalar),
+    }
+}
+
+fn scalars_equal(s1: &Value, s2: &Value) -> bool {
+    match (s1, s2) {
+        (Value::Number(n1), Value::Number(n2)) => numbers_equal(n1, n2),
+        _ => s1 == s2,
+    }
+}
+
+/// Yields the marked nodes that are not inside a subtree marked as a whole,
+/// so a subtree deleted or inserted in one piece is reported once.
+fn whole_subtree_roots<'t>(
+    tree: &'t Tree<'_>,
+    marked: &'t [bool],
+) -> impl Iterator<Item = usize> + 't {
+    let mut prefix = vec![0usize; marked.len() + 1];
+    for (i, &m) in marked.iter().enumerate() {
+        prefix[i + 1] = prefix[i] + m as usize;
+    }
+    let whole: Vec<bool> = tree
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| marked[i] && prefix[i] - prefix[node.leftmost] == i - node.leftmost)
+        .collect();
+
+    // In postorder a node's ancestors come after it, and its parent is the
+    // closest later node whose subtree contains it.
+    let mut parent = vec![None; marked.len()];
+    let mut open: Vec<usize> = Vec::new();
+    for i in (0..tree.nodes.len()).rev() {
+        while let Some(&top) = open.last() {
+            if tree.nodes[top].leftmost <= i {
+                break;
+            }
+            open.pop();
+        }
+        parent[i] = open.last().copied();
+        open.push(i);
+    }
+
+    (0..tree.nodes.len()).filter(move |&i| marked[i] && !parent[i].is_some_and(|p: usize| whole[p]))
+}
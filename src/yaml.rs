@@ -0,0 +1,173 @@
+use serde_json::{Map, Number, Value};
+use yaml_rust2::parser::{Event, Parser, Tag};
+use yaml_rust2::scanner::{Marker, TScalarStyle};
+use yaml_rust2::Yaml;
+
+enum Frame {
+    Sequence(Vec<Value>),
+    Mapping(Map<String, Value>, Option<String>),
+}
+
+/// Parses a single YAML document into the JSON data model.
+///
+/// Constructs that have no JSON equivalent are rejected rather than silently
+/// rewritten: anchors and aliases, non-string mapping keys, duplicate keys,
+/// custom tags and non-finite floats.
+pub fn from_yaml_str(input: &str) -> Result<Value, String> {
+    let mut parser = Parser::new_from_str(input);
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut document: Option<Value> = None;
+    let mut documents = 0;
+
+    loop {
+        let (event, mark) = parser.next_token().map_err(|e| e.to_string())?;
+
+        let node = match event {
+            Event::StreamEnd => break,
+            Event::Nothing | Event::StreamStart | Event::DocumentEnd => continue,
+            Event::DocumentStart => {
+                documents += 1;
+                if documents > 1 {
+                    return Err(error(mark, "expected a single YAML document"));
+                }
+                continue;
+            }
+            Event::Alias(_) => {
+                return Err(error(mark, "aliases are not supported"));
+            }
+            Event::SequenceStart(anchor, tag) => {
+                check_anchor(anchor, mark)?;
+                check_collection_tag(tag.as_ref(), "seq", mark)?;
+                stack.push(Frame::Sequence(Vec::new()));
+                continue;
+            }
+            Event::MappingStart(anchor, tag) => {
+                check_anchor(anchor, mark)?;
+                check_collection_tag(tag.as_ref(), "map", mark)?;
+                stack.push(Frame::Mapping(Map::new(), None));
+                continue;
+            }
+            Event::SequenceEnd => match stack.pop() {
+                Some(Frame::Sequence(items)) => Value::Array(items),
+                _ => return Err(error(mark, "unbalanced sequence")),
+            },
+            Event::MappingEnd => match stack.pop() {
+                Some(Frame::Mapping(map, None)) => Value::Object(map),
+                _ => return Err(error(mark, "unbalanced mapping")),
+            },
+            Event::Scalar(text, style, anchor, tag) => {
+                check_anchor(anchor, mark)?;
+                scalar_to_value(text, style, tag.as_ref(), mark)?
+            }
+        };
+
+        match stack.last_mut() {
+            None => document = Some(node),
+            Some(Frame::Sequence(items)) => items.push(node),
+            Some(Frame::Mapping(map, key)) => match key.take() {
+                None => match node {
+                    Value::String(k) => *key = Some(k),
+                    Value::Array(_) | Value::Object(_) => {
+                        return Err(error(mark, "complex mapping keys are not supported"));
+                    }
+                    other => {
+                        return Err(error(
+                            mark,
+                            &format!("non-string mapping key {} is not supported", other),
+                        ));
+                    }
+                },
+                Some(k) => {
+                    if map.contains_key(&k) {
+                        return Err(error(mark, &format!("duplicate mapping key '{}'", k)));
+                    }
+                    map.insert(k, node);
+                }
+            },
+        }
+    }
+
+    Ok(document.unwrap_or(Value::Null))
+}
+
+fn scalar_to_value(
+    text: String,
+    style: TScalarStyle,
+    tag: Option<&Tag>,
+    mark: Marker,
+) -> Result<Value, String> {
+    let resolved = match tag {
+        Some(tag) if tag.handle == "tag:yaml.org,2002:" => match tag.suffix.as_str() {
+            "str" => return Ok(Value::String(text)),
+            "null" | "bool" | "int" | "float" => {
+                let resolved = Yaml::from_str(&text);
+                let matches_tag = matches!(
+                    (tag.suffix.as_str(), &resolved),
+                    ("null", Yaml::Null)
+                        | ("bool", Yaml::Boolean(_))
+                        | ("int", Yaml::Integer(_))
+                        | ("float", Yaml::Real(_))
+                        | ("float", Yaml::Integer(_))
+                );
+                if !matches_tag {
+                    return Err(error(
+                        mark,
+                        &format!("'{}' is not a valid !!{}", text, tag.suffix),
+                    ));
+                }
+                resolved
+            }
+            other => return Err(error(mark, &format!("unsupported tag !!{}", other))),
+        },
+        Some(tag) => {
+            return Err(error(
+                mark,
+                &format!("unsupported tag {}{}", tag.handle, tag.suffix),
+            ));
+        }
+        None if style != TScalarStyle::Plain => return Ok(Value::String(text)),
+        None => Yaml::from_str(&text),
+    };
+
+    match resolved {
+        Yaml::Null => Ok(Value::Null),
+        Yaml::Boolean(b) => Ok(Value::Bool(b)),
+        Yaml::Integer(i) => Ok(Value::Number(i.into())),
+        Yaml::Real(real) => real
+            .parse::<Number>()
+            .ok()
+            .or_else(|| real.parse::<f64>().ok().and_then(Number::from_f64))
+            .map(Value::Number)
+            .ok_or_else(|| {
+                error(
+                    mark,
+                    &format!("float '{}' cannot be represented in JSON", real),
+                )
+            }),
+        Yaml::String(s) => Ok(Value::String(s)),
+        _ => Ok(Value::String(text)),
+    }
+}
+
+fn check_anchor(anchor: usize, mark: Marker) -> Result<(), String> {
+    if anchor == 0 {
+        Ok(())
+    } else {
+        Err(error(mark, "anchors are not supported"))
+    }
+}
+
+fn check_collection_tag(tag: Option<&Tag>, expected: &str, mark: Marker) -> Result<(), String> {
+    match tag {
+        None => Ok(()),
+        Some(tag) if tag.handle == "tag:yaml.org,2002:" && tag.suffix == expected => Ok(()),
+        Some(tag) => Err(error(
+            mark,
+            &format!("unsupported tag {}{}", tag.handle, tag.suffix),
+        )),
+    }
+}
+
+fn error(mark: Marker, message: &str) -> String {
+    format!("{} at line {} column {}", message, mark.line(), mark.col())
+}